@@ -5,14 +5,13 @@ use std::collections::HashSet;
 use hli::vfc;
 use hli::vfc::Vfc;
 use hli::fc;
-use hli::file;
 use hli::random;
 use hli::vector;
 
 mod game;
 use game::Game;
 
-use file::load_tileset_from_path;
+use hli::file::load_tileset_from_path;
 
 use vector::Vector;
 
@@ -20,7 +19,7 @@ use random::shuffle;
 
 const GAME_NAME: &'static str = "Escape from Castle Dracula";
 
-fn render_to_argb_u32(framebuffer: &[vfc::Rgb; vfc::NUM_SCREEN_PIXELS], target_buffer: &mut [u32]) {
+fn render_to_argb_u32(framebuffer: &[vfc::Rgb], target_buffer: &mut [u32]) {
     for (index, argb) in framebuffer.iter().map(|rgb| rgb.as_argb_u32()).enumerate() {
         target_buffer[index] = argb;
     }
@@ -108,19 +107,10 @@ pub fn new_vfc() -> Vfc {
         */
     ];
 
-    fc.palette = Palette::new(
-        (0..NUM_PALETTE_ENTRIES)
-            .map(|index| {
-                if index < (&preview_palette_array[..]).len() {
-                    preview_palette_array[index]
-                } else {
-                    Rgb::default()
-                }
-            })
-            .collect::<Vec<_>>()
-            .try_into()
-            .unwrap_or_else(|_| unreachable!()),
-    );
+    fc.palette = PaletteBuilder::new()
+        .with_colors(&preview_palette_array)
+        .unwrap()
+        .build();
 
     fc.tileset = load_tileset_from_path("hunt/hunt_tiles.png").unwrap();
 
@@ -1,6 +1,5 @@
 use std::collections::HashMap;
 
-use hli::file;
 use hli::vfc;
 use hli::vfc::Vfc;
 
@@ -9,11 +8,11 @@ use game::Game;
 
 mod plat;
 
-use file::load_tileset_from_path;
+use hli::file::load_tileset_from_path;
 
 const GAME_NAME: &'static str = "Escape from Castle Dracula";
 
-fn render_to_argb_u32(framebuffer: &[vfc::Rgb; vfc::NUM_SCREEN_PIXELS], target_buffer: &mut [u32]) {
+fn render_to_argb_u32(framebuffer: &[vfc::Rgb], target_buffer: &mut [u32]) {
     for (index, argb) in framebuffer.iter().map(|rgb| rgb.as_argb_u32()).enumerate() {
         target_buffer[index] = argb;
     }
@@ -105,19 +104,10 @@ pub fn new_vfc() -> Vfc {
         Rgb::new(0xee, 0xee, 0xdd), // White
     ];
 
-    fc.palette = Palette::new(
-        (0..NUM_PALETTE_ENTRIES)
-            .map(|index| {
-                if index < (&preview_palette_array[..]).len() {
-                    preview_palette_array[index]
-                } else {
-                    Rgb::default()
-                }
-            })
-            .collect::<Vec<_>>()
-            .try_into()
-            .unwrap_or_else(|_| unreachable!()),
-    );
+    fc.palette = PaletteBuilder::new()
+        .with_colors(&preview_palette_array)
+        .unwrap()
+        .build();
 
     fc.tileset = load_tileset_from_path("escape_tiles.png").unwrap();
 
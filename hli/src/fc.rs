@@ -1,29 +1,25 @@
 use vfc::*;
 
-fn get_index_from_coords(x: usize, y: usize) -> usize {
-    (y % BG_HEIGHT) * BG_WIDTH + x % BG_WIDTH
-}
-
 pub fn poke_bg(bg: usize, fc: &mut Vfc, x: usize, y: usize, tile_index: TileIndex) {
-    let i = get_index_from_coords(x, y);
+    let i = fc.bg_layers[bg].get_map_index(x, y);
 
     fc.bg_layers[bg].tiles[i] = tile_index;
 }
 
 pub fn poke_bg_rotation(bg: usize, fc: &mut Vfc, x: usize, y: usize, rotation: u8) {
-    let i = get_index_from_coords(x, y);
+    let i = fc.bg_layers[bg].get_map_index(x, y);
 
     fc.bg_layers[bg].attributes[i].set_rotation(rotation);
 }
 
 pub fn peek_bg(bg: usize, fc: &Vfc, x: usize, y: usize) -> TileIndex {
-    let i = get_index_from_coords(x, y);
+    let i = fc.bg_layers[bg].get_map_index(x, y);
 
     fc.bg_layers[bg].tiles[i]
 }
 
 pub fn poke_bg_palette(bg: usize, fc: &mut Vfc, x: usize, y: usize, palette_index: Subpalette) {
-    let i = get_index_from_coords(x, y);
+    let i = fc.bg_layers[bg].get_map_index(x, y);
 
     fc.bg_layers[bg].attributes[i].set_palette(palette_index);
 }
@@ -41,7 +37,7 @@ pub fn peek_main_bg(fc: &vfc::Vfc, x: usize, y: usize) -> TileIndex {
 }
 
 pub fn peek_game_layer(fc: &vfc::Vfc, x: usize, y: usize) -> TileIndex {
-    fc.bg_layers[0].tiles[(y % vfc::BG_HEIGHT) * vfc::BG_WIDTH + x % vfc::BG_WIDTH]
+    peek_bg(0, fc, x, y)
 }
 
 pub fn poke_aux_bg(fc: &mut vfc::Vfc, x: usize, y: usize, tile_index: TileIndex) {
@@ -92,23 +88,24 @@ pub fn clear_rect_palette(bg: usize, fc: &mut Vfc, x: usize, y: usize, w: usize,
 }
 
 pub fn clear_bg_tiles(bg: usize, fc: &mut Vfc) {
-    clear_rect_tiles(bg, fc, 0, 0, BG_WIDTH, BG_HEIGHT);
+    let (width, height) = (fc.bg_layers[bg].width(), fc.bg_layers[bg].height());
+    clear_rect_tiles(bg, fc, 0, 0, width, height);
 }
 
 pub fn clear_bg_palette(bg: usize, fc: &mut Vfc) {
-    clear_rect_palette(bg, fc, 0, 0, BG_WIDTH, BG_HEIGHT);
+    let (width, height) = (fc.bg_layers[bg].width(), fc.bg_layers[bg].height());
+    clear_rect_palette(bg, fc, 0, 0, width, height);
 }
 
 pub fn clear_line(bg: usize, fc: &mut Vfc, line: usize) {
-    for xi in 0..BG_WIDTH {
+    for xi in 0..fc.bg_layers[bg].width() {
         poke_bg(bg, fc, xi, line, TileIndex(0x0));
         //~ poke_bg_palette(bg, fc, xi, line, Subpalette::new(1));
     }
 }
 
 pub fn clear_sprites(fc: &mut Vfc) {
-    let range = 0..=255;
-    for i in range {
-        fc.oam.0[i].hide();
+    for entry in fc.oam.0.iter_mut() {
+        entry.hide();
     }
 }
@@ -1,74 +1,764 @@
-use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
 
-use vfc::Tileset;
+use image::RgbaImage;
 
-pub fn load_tileset_from_path(path: &str) -> Result<Tileset, Box<dyn Error>> {
-    use image::io::Reader as ImageReader;
-    use vfc::*;
+use vfc::*;
 
+// how the colours of a tile sheet become tile pixels. pixels with alpha under 128 are always
+// pixel 0, the transparent one
+#[derive(Clone)]
+pub enum ColorMapping {
+    // the brightness of each pixel, split evenly into TILE_PALETTE_SIZE steps. opaque black
+    // ends up as pixel 0 too
+    Grayscale,
+    // like Grayscale, from the red channel alone. what the loaders have always done
+    RedChannel,
+    // every colour has to be one of the colours of the subpalette. a tile with more colours
+    // than that is an error
+    Exact(Palette, Subpalette),
+    // like Exact, but each tile uses whichever subpalette has all of its colours
+    ExactAny(Palette),
+    // every colour becomes the closest colour of the subpalette
+    Nearest(Palette, Subpalette),
+}
+
+// a tile of a sheet, named by where it is so the sheet can be fixed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SheetTile {
+    pub tile_index: TileIndex,
+    pub column: usize,
+    pub row: usize,
+}
+
+impl std::fmt::Display for SheetTile {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "tile {:#04x} (column {}, row {})",
+            self.tile_index.0, self.column, self.row
+        )
+    }
+}
+
+#[derive(Debug)]
+pub enum TilesetImportError {
+    Image(image::ImageError),
+    // more opaque colours than a subpalette has room for
+    TooManyColors {
+        tile: SheetTile,
+        colors: usize,
+    },
+    // a colour that isn't in the subpalette, at a pixel of the tile
+    UnmatchedColor {
+        tile: SheetTile,
+        x: usize,
+        y: usize,
+        color: Rgb,
+    },
+    // no single subpalette has every colour of the tile
+    NoMatchingSubpalette(SheetTile),
+}
+
+impl std::fmt::Display for TilesetImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TilesetImportError::Image(e) => write!(f, "{e}"),
+            TilesetImportError::TooManyColors { tile, colors } => write!(
+                f,
+                "{tile} uses {colors} colours, a subpalette only has room for {}",
+                TILE_PALETTE_SIZE - 1
+            ),
+            TilesetImportError::UnmatchedColor { tile, x, y, color } => write!(
+                f,
+                "{tile} has the colour #{:02x}{:02x}{:02x} at ({x}, {y}), which isn't in the subpalette",
+                color.r(),
+                color.g(),
+                color.b()
+            ),
+            TilesetImportError::NoMatchingSubpalette(tile) => {
+                write!(f, "{tile} doesn't fit in any one subpalette")
+            }
+        }
+    }
+}
+
+impl std::error::Error for TilesetImportError {}
+
+impl From<image::ImageError> for TilesetImportError {
+    fn from(e: image::ImageError) -> TilesetImportError {
+        TilesetImportError::Image(e)
+    }
+}
+
+pub fn load_tileset_from_path<P: AsRef<Path>>(path: P) -> Result<Tileset, TilesetImportError> {
     let mut tileset = Tileset::default();
 
-    let raw_img = ImageReader::open(path)?.decode()?;
+    load_tileset_bank_from_path(&mut tileset, TileBank(0), path)?;
+
+    Ok(tileset)
+}
+
+// load a sheet of up to 256 tiles into one bank of an existing tileset
+pub fn load_tileset_bank_from_path<P: AsRef<Path>>(
+    tileset: &mut Tileset,
+    bank: TileBank,
+    path: P,
+) -> Result<(), TilesetImportError> {
+    import_tileset_from_path(tileset, bank, path, &ColorMapping::RedChannel)?;
+
+    Ok(())
+}
+
+// like load_tileset_bank_from_path, with a choice of how colours are mapped. returns the
+// subpalette of each tile, in tile order
+pub fn import_tileset_from_path<P: AsRef<Path>>(
+    tileset: &mut Tileset,
+    bank: TileBank,
+    path: P,
+    mapping: &ColorMapping,
+) -> Result<Vec<Subpalette>, TilesetImportError> {
+    let img = image::open(path)?.into_rgba8();
+
+    import_tileset(tileset, bank, &img, mapping)
+}
 
-    // first, we load the image
-    let img = raw_img.into_rgba8();
-    // check the dimensions
+// the sheet is read left to right, top to bottom. tiles past the end of the bank are ignored,
+// and nothing is written unless every tile imports
+pub fn import_tileset(
+    tileset: &mut Tileset,
+    bank: TileBank,
+    img: &RgbaImage,
+    mapping: &ColorMapping,
+) -> Result<Vec<Subpalette>, TilesetImportError> {
     let (image_width, image_height) = img.dimensions();
 
     let tile_columns = image_width as usize / TILE_WIDTH; // (rounds down)
     let tile_rows = image_height as usize / TILE_HEIGHT; // (rounds down)
 
-    // iterate each tile index
-
-    for column in 0..tile_columns {
-        for row in 0..tile_rows {
-            let tile_index = column + row * tile_rows;
-
-            let tile_x = column * TILE_WIDTH;
-            let tile_y = row * TILE_HEIGHT;
-
-            // NOTE: this only works for a TILE_WIDTH of 8
-            for pixel_y in 0..TILE_HEIGHT {
-                let mut bytes = [
-                    [0, 0, 0],
-                    [0, 0, 0],
-                    [0, 0, 0],
-                    [0, 0, 0],
-                    [0, 0, 0],
-                    [0, 0, 0],
-                    [0, 0, 0],
-                    [0, 0, 0],
-                ];
-
-                for pixel_x in 0..TILE_WIDTH {
-                    let pixel = img.get_pixel((pixel_x + tile_x) as u32, (pixel_y + tile_y) as u32);
-
-                    let [r, _g, _b, a] = pixel.0;
-
-                    //~ print!("{r} ");
-
-                    //~ let color_index = 0;
-                    //~ /*
-                    let color_index = if a < 128 {
-                        0
-                    } else {
-                        r / (256 / TILE_PALETTE_SIZE) as u8
-                    };
-                    //~ */
-                    for plane_index in 0..NUM_PLANES {
-                        let bit = (color_index & (1 << plane_index)) >> plane_index;
-
-                        bytes[pixel_y][NUM_PLANES - 1 - plane_index] |= bit << pixel_x;
+    let mut tiles = vec![];
+
+    for row in 0..tile_rows {
+        for column in 0..tile_columns {
+            let tile_index = column + row * tile_columns;
+
+            if tile_index >= TILES_PER_BANK {
+                continue;
+            }
+
+            let tile = SheetTile {
+                tile_index: TileIndex(tile_index as u8),
+                column,
+                row,
+            };
+
+            let mut colors = [[None; TILE_WIDTH]; TILE_HEIGHT];
+
+            for (pixel_y, colors_row) in colors.iter_mut().enumerate() {
+                for (pixel_x, color) in colors_row.iter_mut().enumerate() {
+                    let x = column * TILE_WIDTH + pixel_x;
+                    let y = row * TILE_HEIGHT + pixel_y;
+
+                    let [r, g, b, a] = img.get_pixel(x as u32, y as u32).0;
+
+                    if a >= 128 {
+                        *color = Some(Rgb::new(r, g, b));
                     }
                 }
+            }
+
+            tiles.push((tile, map_tile(&tile, &colors, mapping)?));
+        }
+    }
 
-                for plane_index in 0..NUM_PLANES {
-                    tileset.pixel_data[plane_index][tile_index][pixel_y] =
-                        bytes[pixel_y][plane_index];
+    let mut subpalettes = vec![];
+
+    for (tile, (pixels, subpalette)) in tiles {
+        let tile_index = bank.tileset_index(tile.tile_index);
+
+        for (pixel_y, pixels_row) in pixels.iter().enumerate() {
+            let mut bytes = [0; NUM_PLANES];
+
+            for (pixel_x, &pixel) in pixels_row.iter().enumerate() {
+                for (plane_index, byte) in bytes.iter_mut().enumerate() {
+                    // plane 0 holds the high bit
+                    let bit = (pixel >> (NUM_PLANES - 1 - plane_index)) & 1;
+
+                    *byte |= bit << pixel_x;
                 }
             }
+
+            for (plane_index, byte) in bytes.into_iter().enumerate() {
+                tileset.pixel_data_mut()[plane_index][tile_index][pixel_y] = byte;
+            }
         }
+
+        subpalettes.push(subpalette);
     }
 
-    Ok(tileset)
+    Ok(subpalettes)
+}
+
+type TilePixels = [[u8; TILE_WIDTH]; TILE_HEIGHT];
+
+// None is a transparent pixel
+fn map_tile(
+    tile: &SheetTile,
+    colors: &[[Option<Rgb>; TILE_WIDTH]; TILE_HEIGHT],
+    mapping: &ColorMapping,
+) -> Result<(TilePixels, Subpalette), TilesetImportError> {
+    let map_with = |map: &dyn Fn(Rgb) -> Option<u8>| -> Result<TilePixels, (usize, usize, Rgb)> {
+        let mut pixels = [[0; TILE_WIDTH]; TILE_HEIGHT];
+
+        for (pixel_y, colors_row) in colors.iter().enumerate() {
+            for (pixel_x, color) in colors_row.iter().enumerate() {
+                if let &Some(color) = color {
+                    pixels[pixel_y][pixel_x] = map(color).ok_or((pixel_x, pixel_y, color))?;
+                }
+            }
+        }
+
+        Ok(pixels)
+    };
+
+    // only the exact mappings care, the others make any number of colours fit
+    let check_colors = || {
+        let mut unique_colors: Vec<Rgb> = vec![];
+        for &color in colors.iter().flatten().flatten() {
+            if !unique_colors.contains(&color) {
+                unique_colors.push(color);
+            }
+        }
+
+        if unique_colors.len() >= TILE_PALETTE_SIZE {
+            return Err(TilesetImportError::TooManyColors {
+                tile: *tile,
+                colors: unique_colors.len(),
+            });
+        }
+
+        Ok(())
+    };
+
+    match mapping {
+        ColorMapping::Grayscale => {
+            let pixels = map_with(&|color| Some(grayscale_pixel(color))).unwrap_or_default();
+            Ok((pixels, Subpalette::default()))
+        }
+        ColorMapping::RedChannel => {
+            let pixels = map_with(&|color| Some(red_channel_pixel(color))).unwrap_or_default();
+            Ok((pixels, Subpalette::default()))
+        }
+        ColorMapping::Exact(palette, subpalette) => {
+            check_colors()?;
+
+            match map_with(&|color| exact_pixel(palette, *subpalette, color)) {
+                Ok(pixels) => Ok((pixels, *subpalette)),
+                Err((x, y, color)) => Err(TilesetImportError::UnmatchedColor {
+                    tile: *tile,
+                    x,
+                    y,
+                    color,
+                }),
+            }
+        }
+        ColorMapping::ExactAny(palette) => {
+            check_colors()?;
+
+            let num_subpalettes = palette.len().div_ceil(SUBPALETTE_SIZE);
+
+            (0..num_subpalettes)
+                .map(|subpalette| Subpalette::new(subpalette as u8))
+                .find_map(|subpalette| {
+                    map_with(&|color| exact_pixel(palette, subpalette, color))
+                        .ok()
+                        .map(|pixels| (pixels, subpalette))
+                })
+                .ok_or(TilesetImportError::NoMatchingSubpalette(*tile))
+        }
+        ColorMapping::Nearest(palette, subpalette) => {
+            let pixels = map_with(&|color| Some(nearest_pixel(palette, *subpalette, color)))
+                .unwrap_or_default();
+            Ok((pixels, *subpalette))
+        }
+    }
+}
+
+fn grayscale_pixel(color: Rgb) -> u8 {
+    let luma = (color.r() as u32 * 299 + color.g() as u32 * 587 + color.b() as u32 * 114) / 1000;
+
+    (luma / (256 / TILE_PALETTE_SIZE) as u32) as u8
+}
+
+fn red_channel_pixel(color: Rgb) -> u8 {
+    color.r() / (256 / TILE_PALETTE_SIZE) as u8
+}
+
+// the opaque colours of a subpalette, with their pixel values
+fn subpalette_colors(
+    palette: &Palette,
+    subpalette: Subpalette,
+) -> impl Iterator<Item = (u8, Rgb)> + '_ {
+    (1..TILE_PALETTE_SIZE as u8).map(move |pixel| {
+        let index = subpalette.get() * SUBPALETTE_SIZE as u8 + pixel;
+        (pixel, palette[PaletteIndex(index)])
+    })
+}
+
+fn exact_pixel(palette: &Palette, subpalette: Subpalette, color: Rgb) -> Option<u8> {
+    subpalette_colors(palette, subpalette)
+        .find(|&(_, entry)| entry == color)
+        .map(|(pixel, _)| pixel)
+}
+
+fn nearest_pixel(palette: &Palette, subpalette: Subpalette, color: Rgb) -> u8 {
+    let distance = |entry: Rgb| {
+        [
+            (entry.r(), color.r()),
+            (entry.g(), color.g()),
+            (entry.b(), color.b()),
+        ]
+        .iter()
+        .map(|&(a, b)| (a as i32 - b as i32).pow(2))
+        .sum::<i32>()
+    };
+
+    subpalette_colors(palette, subpalette)
+        .min_by_key(|&(_, entry)| distance(entry))
+        .map(|(pixel, _)| pixel)
+        .unwrap_or_default()
+}
+
+// tilesets and maps back out to images
+
+// tile sheets are 16 tiles wide, so a whole bank is a 128x128 image
+pub const SHEET_COLUMNS: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SheetFormat {
+    // a paletted png of the subpalette's colours, with pixel 0 transparent
+    Indexed,
+    // an rgba png, with pixel 0 transparent
+    Rgb,
+}
+
+#[derive(Debug)]
+pub enum ExportError {
+    Io(std::io::Error),
+    Image(image::ImageError),
+    Png(png::EncodingError),
+}
+
+impl std::fmt::Display for ExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ExportError::Io(e) => write!(f, "{e}"),
+            ExportError::Image(e) => write!(f, "{e}"),
+            ExportError::Png(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {}
+
+impl From<std::io::Error> for ExportError {
+    fn from(e: std::io::Error) -> ExportError {
+        ExportError::Io(e)
+    }
+}
+
+impl From<image::ImageError> for ExportError {
+    fn from(e: image::ImageError) -> ExportError {
+        ExportError::Image(e)
+    }
+}
+
+impl From<png::EncodingError> for ExportError {
+    fn from(e: png::EncodingError) -> ExportError {
+        ExportError::Png(e)
+    }
+}
+
+// the ramp ColorMapping::Grayscale and RedChannel read back, for exporting tiles that have no palette yet
+pub fn grayscale_palette() -> Palette {
+    let step = 256 / TILE_PALETTE_SIZE;
+
+    Palette::new(
+        (0..TILE_PALETTE_SIZE)
+            .map(|pixel| {
+                let gray = (pixel * step) as u8;
+                Rgb::new(gray, gray, gray)
+            })
+            .collect(),
+    )
+}
+
+// the raw pixels of every tile in a bank, as a sheet laid out the way import_tileset reads it
+fn tileset_pixels(tileset: &Tileset, bank: TileBank) -> Vec<u8> {
+    let sheet_width = SHEET_COLUMNS * TILE_WIDTH;
+    let sheet_height = TILES_PER_BANK.div_ceil(SHEET_COLUMNS) * TILE_HEIGHT;
+    let mut pixels = vec![0; sheet_width * sheet_height];
+
+    for tile in 0..TILES_PER_BANK {
+        let (column, row) = (tile % SHEET_COLUMNS, tile / SHEET_COLUMNS);
+
+        for pixel_y in 0..TILE_HEIGHT {
+            for pixel_x in 0..TILE_WIDTH {
+                let pixel =
+                    tileset.get_pixel(bank, TileIndex(tile as u8), pixel_x as u8, pixel_y as u8);
+
+                let x = column * TILE_WIDTH + pixel_x;
+                let y = row * TILE_HEIGHT + pixel_y;
+                pixels[y * sheet_width + x] = pixel.0;
+            }
+        }
+    }
+
+    pixels
+}
+
+fn rgba(palette: &Palette, index: PaletteIndex, pixel: RawPixel) -> image::Rgba<u8> {
+    if pixel == RawPixel(0) {
+        return image::Rgba([0, 0, 0, 0]);
+    }
+
+    let rgb = palette[index];
+    image::Rgba([rgb.r(), rgb.g(), rgb.b(), 255])
+}
+
+// every tile of a bank in the colours of a subpalette
+pub fn tileset_sheet(
+    tileset: &Tileset,
+    bank: TileBank,
+    palette: &Palette,
+    subpalette: Subpalette,
+) -> RgbaImage {
+    let sheet_width = SHEET_COLUMNS * TILE_WIDTH;
+    let pixels = tileset_pixels(tileset, bank);
+
+    RgbaImage::from_fn(
+        sheet_width as u32,
+        (pixels.len() / sheet_width) as u32,
+        |x, y| {
+            let pixel = RawPixel(pixels[y as usize * sheet_width + x as usize]);
+            rgba(palette, subpalette.colorize_pixel(pixel), pixel)
+        },
+    )
+}
+
+pub fn write_tileset_png<W: Write>(
+    writer: W,
+    tileset: &Tileset,
+    bank: TileBank,
+    palette: &Palette,
+    subpalette: Subpalette,
+    format: SheetFormat,
+) -> Result<(), ExportError> {
+    let sheet_width = SHEET_COLUMNS * TILE_WIDTH;
+    let pixels = tileset_pixels(tileset, bank);
+
+    let mut encoder = png::Encoder::new(
+        writer,
+        sheet_width as u32,
+        (pixels.len() / sheet_width) as u32,
+    );
+    encoder.set_depth(png::BitDepth::Eight);
+
+    match format {
+        SheetFormat::Indexed => {
+            let colors = (0..TILE_PALETTE_SIZE as u8)
+                .flat_map(|pixel| {
+                    let rgb = palette[subpalette.colorize_pixel(RawPixel(pixel))];
+                    [rgb.r(), rgb.g(), rgb.b()]
+                })
+                .collect::<Vec<_>>();
+
+            encoder.set_color(png::ColorType::Indexed);
+            encoder.set_palette(colors);
+            // only the first entry is transparent, the rest default to opaque
+            encoder.set_trns(vec![0]);
+            encoder.write_header()?.write_image_data(&pixels)?;
+        }
+        SheetFormat::Rgb => {
+            let sheet = tileset_sheet(tileset, bank, palette, subpalette);
+
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.write_header()?.write_image_data(sheet.as_raw())?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn save_tileset_png<P: AsRef<Path>>(
+    path: P,
+    tileset: &Tileset,
+    bank: TileBank,
+    palette: &Palette,
+    subpalette: Subpalette,
+    format: SheetFormat,
+) -> Result<(), ExportError> {
+    let writer = BufWriter::new(File::create(path)?);
+
+    write_tileset_png(writer, tileset, bank, palette, subpalette, format)
+}
+
+// the whole map with every tile's palette and flips, unscrolled. pixel 0 is transparent
+pub fn bg_layer_image(layer: &BgLayer, tileset: &Tileset, palette: &Palette) -> RgbaImage {
+    RgbaImage::from_fn(
+        layer.pixel_width() as u32,
+        layer.pixel_height() as u32,
+        |x, y| {
+            let (x, y) = (x as usize, y as usize);
+            let map_index = layer.get_map_index(x / TILE_WIDTH, y / TILE_HEIGHT);
+            let attributes = &layer.attributes[map_index];
+
+            let pixel = tileset.get_pixel_rotated(
+                layer.tile_bank,
+                layer.tiles[map_index],
+                attributes,
+                (x % TILE_WIDTH) as u8,
+                (y % TILE_HEIGHT) as u8,
+            );
+
+            rgba(
+                palette,
+                attributes.get_palette().colorize_pixel(pixel),
+                pixel,
+            )
+        },
+    )
+}
+
+pub fn save_bg_layer_png<P: AsRef<Path>>(
+    path: P,
+    layer: &BgLayer,
+    tileset: &Tileset,
+    palette: &Palette,
+) -> Result<(), ExportError> {
+    bg_layer_image(layer, tileset, palette).save(path)?;
+
+    Ok(())
+}
+
+// the tile indices of a map, one line per row of tiles, comma separated
+pub fn bg_layer_tiles_csv(layer: &BgLayer) -> String {
+    bg_layer_csv(layer, |map_index| layer.tiles[map_index].0)
+}
+
+// the attributes of a map in the same layout, each one packed back into its byte
+pub fn bg_layer_attributes_csv(layer: &BgLayer) -> String {
+    bg_layer_csv(layer, |map_index| layer.attributes[map_index].to_byte())
+}
+
+fn bg_layer_csv<F: Fn(usize) -> u8>(layer: &BgLayer, value: F) -> String {
+    let mut csv = String::new();
+
+    for tile_y in 0..layer.height() {
+        let row = (0..layer.width())
+            .map(|tile_x| value(layer.get_map_index(tile_x, tile_y)).to_string())
+            .collect::<Vec<_>>();
+
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_palette() -> Palette {
+        let mut palette = Palette::with_size(NUM_PALETTE_ENTRIES);
+        for index in 0..NUM_PALETTE_ENTRIES {
+            palette[PaletteIndex(index as u8)] = Rgb::new(index as u8 * 4, 0, 0);
+        }
+        palette
+    }
+
+    fn color_at(palette: &Palette, index: u8) -> image::Rgba<u8> {
+        let rgb = palette[PaletteIndex(index)];
+        image::Rgba([rgb.r(), rgb.g(), rgb.b(), 255])
+    }
+
+    fn pixel(tileset: &Tileset, tile_index: usize, x: usize, y: usize) -> u8 {
+        (0..NUM_PLANES).fold(0, |pixel, plane| {
+            let bit = (tileset.pixel_data()[plane][tile_index][y] >> x) & 1;
+            (pixel << 1) | bit
+        })
+    }
+
+    #[test]
+    fn grayscale_sheet_order() {
+        // 3 tiles wide and 2 tall, so rows are 3 tiles apart
+        let mut img = RgbaImage::new(3 * TILE_WIDTH as u32, 2 * TILE_HEIGHT as u32);
+        for tile in 0..6 {
+            let (column, row) = (tile % 3, tile / 3);
+            let gray = tile as u8 * 32;
+            img.put_pixel(
+                (column * TILE_WIDTH + 1) as u32,
+                (row * TILE_HEIGHT + 2) as u32,
+                image::Rgba([gray, gray, gray, 255]),
+            );
+        }
+
+        let mut tileset = Tileset::default();
+        import_tileset(&mut tileset, TileBank(1), &img, &ColorMapping::Grayscale).unwrap();
+
+        for tile in 0..6 {
+            let tile_index = TileBank(1).tileset_index(TileIndex(tile as u8));
+            assert_eq!(pixel(&tileset, tile_index, 1, 2), tile as u8);
+            assert_eq!(pixel(&tileset, tile_index, 2, 2), 0);
+        }
+    }
+
+    #[test]
+    fn palette_mappings() {
+        let palette = test_palette();
+
+        // tile 0 uses subpalette 2, tile 1 uses subpalette 5
+        let mut img = RgbaImage::new(2 * TILE_WIDTH as u32, TILE_HEIGHT as u32);
+        for pixel in 1..8 {
+            img.put_pixel(pixel, 0, color_at(&palette, 2 * 8 + pixel as u8));
+            img.put_pixel(8 + pixel, 0, color_at(&palette, 5 * 8 + pixel as u8));
+        }
+
+        let mut tileset = Tileset::default();
+        let subpalettes = import_tileset(
+            &mut tileset,
+            TileBank(0),
+            &img,
+            &ColorMapping::ExactAny(palette.clone()),
+        )
+        .unwrap();
+        assert_eq!(subpalettes, [Subpalette::new(2), Subpalette::new(5)]);
+        for x in 0..8 {
+            assert_eq!(pixel(&tileset, 0, x, 0), x as u8);
+            assert_eq!(pixel(&tileset, 1, x, 0), x as u8);
+        }
+
+        let exact = ColorMapping::Exact(palette.clone(), Subpalette::new(2));
+        match import_tileset(&mut tileset, TileBank(0), &img, &exact) {
+            Err(TilesetImportError::UnmatchedColor { tile, x, y, .. }) => {
+                assert_eq!(tile.tile_index, TileIndex(1));
+                assert_eq!((x, y), (1, 0));
+            }
+            _ => panic!("tile 1 isn't in subpalette 2"),
+        }
+
+        // off by one step of red is closest to the colour it was nudged from
+        img.put_pixel(3, 0, image::Rgba([(2 * 8 + 6) * 4 + 1, 0, 0, 255]));
+        let nearest = ColorMapping::Nearest(palette, Subpalette::new(2));
+        import_tileset(&mut tileset, TileBank(0), &img, &nearest).unwrap();
+        assert_eq!(pixel(&tileset, 0, 3, 0), 6);
+    }
+
+    #[test]
+    fn too_many_colors() {
+        let palette = test_palette();
+
+        let mut img = RgbaImage::new(2 * TILE_WIDTH as u32, 2 * TILE_HEIGHT as u32);
+        for x in 0..8 {
+            img.put_pixel(8 + x, 8, image::Rgba([x as u8, 0, 0, 255]));
+        }
+
+        let mut tileset = Tileset::default();
+        let exact = ColorMapping::Exact(palette.clone(), Subpalette::new(0));
+        match import_tileset(&mut tileset, TileBank(0), &img, &exact) {
+            Err(TilesetImportError::TooManyColors { tile, colors }) => {
+                assert_eq!(
+                    tile,
+                    SheetTile {
+                        tile_index: TileIndex(3),
+                        column: 1,
+                        row: 1
+                    }
+                );
+                assert_eq!(colors, 8);
+            }
+            _ => panic!("tile 3 has 8 opaque colours"),
+        }
+
+        // nearest squeezes them into the subpalette instead
+        let nearest = ColorMapping::Nearest(palette, Subpalette::new(0));
+        assert!(import_tileset(&mut tileset, TileBank(0), &img, &nearest).is_ok());
+    }
+
+    #[test]
+    fn tileset_round_trip() {
+        let palette = test_palette();
+
+        // every pixel value somewhere, in a different spot in each tile
+        let mut tileset = Tileset::default();
+        for tile in 0..TILES_PER_BANK {
+            for y in 0..TILE_HEIGHT {
+                for plane in 0..NUM_PLANES {
+                    let byte = (tile * 37 + y * 11 + plane * 5) as u8;
+                    tileset.pixel_data_mut()[plane]
+                        [TileBank(1).tileset_index(TileIndex(tile as u8))][y] =
+                        byte.rotate_left(plane as u32);
+                }
+            }
+        }
+
+        let exact = ColorMapping::Exact(palette.clone(), Subpalette::new(3));
+        for format in [SheetFormat::Indexed, SheetFormat::Rgb] {
+            let mut png = vec![];
+            write_tileset_png(
+                &mut png,
+                &tileset,
+                TileBank(1),
+                &palette,
+                Subpalette::new(3),
+                format,
+            )
+            .unwrap();
+
+            let img = image::load_from_memory(&png).unwrap().into_rgba8();
+            let mut imported = Tileset::default();
+            import_tileset(&mut imported, TileBank(1), &img, &exact).unwrap();
+
+            assert_eq!(imported.pixel_data(), tileset.pixel_data());
+        }
+
+        let sheet = tileset_sheet(
+            &tileset,
+            TileBank(1),
+            &grayscale_palette(),
+            Subpalette::new(0),
+        );
+        for mapping in [ColorMapping::Grayscale, ColorMapping::RedChannel] {
+            let mut imported = Tileset::default();
+            import_tileset(&mut imported, TileBank(1), &sheet, &mapping).unwrap();
+            assert_eq!(imported.pixel_data(), tileset.pixel_data());
+        }
+    }
+
+    #[test]
+    fn bg_layer_export() {
+        let palette = test_palette();
+
+        let mut tileset = Tileset::default();
+        // only the top left pixel of tile 1 is set
+        tileset.write_tile(TileIndex(1), [[1, 0, 0, 0, 0, 0, 0, 0], [0; 8], [0; 8]]);
+
+        let mut layer = BgLayer::new(4, 2);
+        let map_index = layer.get_map_index(2, 1);
+        layer.tiles[map_index] = TileIndex(1);
+        // flipped in x and y, so the pixel ends up in the bottom right
+        layer.attributes[map_index] = TileAttributes::default()
+            .with_palette(Subpalette::new(6))
+            .with_rotation(0b011);
+
+        let img = bg_layer_image(&layer, &tileset, &palette);
+        assert_eq!(img.dimensions(), (32, 16));
+        assert_eq!(*img.get_pixel(23, 15), color_at(&palette, 6 * 8 + 4));
+        assert_eq!(img.get_pixel(16, 8).0[3], 0);
+
+        assert_eq!(bg_layer_tiles_csv(&layer), "0,0,0,0\n0,0,1,0\n");
+        assert_eq!(bg_layer_attributes_csv(&layer), "0,0,0,0\n0,0,30,0\n");
+    }
 }
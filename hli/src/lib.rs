@@ -3,6 +3,8 @@ pub use vfc;
 pub mod fc;
 pub mod file;
 pub mod random;
+pub mod streamer;
+pub mod tiled;
 pub mod vector;
 
 #[cfg(test)]
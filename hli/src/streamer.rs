@@ -0,0 +1,239 @@
+// worlds bigger than a bg layer, streamed into it as the camera moves.
+//
+// the bg layer is used as a ring buffer: world tile (x, y) always goes to the map tile at
+// (x % width, y % height), and the layer's scroll is set so the camera's corner of the world
+// lands at the top left of the screen. only the rows and columns that scrolled into view
+// since the last update are written. the layer has to be at least one tile wider and taller
+// than the screen, and its pixel size has to divide 256 so the scroll wraps with it
+
+use vfc::*;
+
+// a rectangle of world tiles, both ends included
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct TileRect {
+    left: usize,
+    top: usize,
+    right: usize,
+    bottom: usize,
+}
+
+impl TileRect {
+    fn contains(&self, tile_x: usize, tile_y: usize) -> bool {
+        (self.left..=self.right).contains(&tile_x) && (self.top..=self.bottom).contains(&tile_y)
+    }
+}
+
+pub struct TilemapStreamer {
+    // the whole world, any size. the tile bank and registers of this layer aren't used
+    pub world: BgLayer,
+    screen_width: usize,
+    screen_height: usize,
+    // the top left of the screen, in world pixels
+    camera_x: usize,
+    camera_y: usize,
+    // what's in the ring buffer, None until the first update
+    loaded: Option<TileRect>,
+}
+
+impl TilemapStreamer {
+    // panics if the config's bg layers can't be used as a ring buffer for its screen
+    pub fn new(world: BgLayer, config: &VfcConfig) -> TilemapStreamer {
+        check_ring_size(
+            config.bg_width,
+            config.bg_height,
+            config.screen_width,
+            config.screen_height,
+        );
+
+        TilemapStreamer {
+            world,
+            screen_width: config.screen_width,
+            screen_height: config.screen_height,
+            camera_x: 0,
+            camera_y: 0,
+            loaded: None,
+        }
+    }
+
+    pub fn camera(&self) -> (usize, usize) {
+        (self.camera_x, self.camera_y)
+    }
+
+    // move the camera, stopping at the edges of the world
+    pub fn set_camera(&mut self, x: usize, y: usize) {
+        let max_x = self.world.pixel_width().saturating_sub(self.screen_width);
+        let max_y = self.world.pixel_height().saturating_sub(self.screen_height);
+
+        self.camera_x = x.min(max_x);
+        self.camera_y = y.min(max_y);
+    }
+
+    // center the camera on a world pixel, e.g. the player
+    pub fn follow(&mut self, x: usize, y: usize) {
+        self.set_camera(
+            x.saturating_sub(self.screen_width / 2),
+            y.saturating_sub(self.screen_height / 2),
+        );
+    }
+
+    // write everything again on the next update, after changing `world` or the layer
+    pub fn invalidate(&mut self) {
+        self.loaded = None;
+    }
+
+    // bring the layer up to date with the camera. returns how many tiles were written
+    pub fn update(&mut self, layer: &mut BgLayer) -> usize {
+        check_ring_size(
+            layer.width(),
+            layer.height(),
+            self.screen_width,
+            self.screen_height,
+        );
+
+        let visible = self.visible_tiles();
+        let mut written = 0;
+
+        for tile_y in visible.top..=visible.bottom {
+            for tile_x in visible.left..=visible.right {
+                if self
+                    .loaded
+                    .is_some_and(|loaded| loaded.contains(tile_x, tile_y))
+                {
+                    continue;
+                }
+
+                let world_index = self.world.get_map_index(tile_x, tile_y);
+                let ring_index = layer.get_map_index(tile_x, tile_y);
+
+                layer.tiles[ring_index] = self.world.tiles[world_index];
+                layer.attributes[ring_index] = self.world.attributes[world_index];
+                written += 1;
+            }
+        }
+
+        self.loaded = Some(visible);
+
+        // screen x shows map x - scroll x, so scrolling by minus the camera puts the camera's
+        // world pixel at the left edge
+        layer.x = (self.camera_x as u8).wrapping_neg();
+        layer.y = (self.camera_y as u8).wrapping_neg();
+
+        written
+    }
+
+    fn visible_tiles(&self) -> TileRect {
+        let last_x = self.world.width().saturating_sub(1);
+        let last_y = self.world.height().saturating_sub(1);
+
+        TileRect {
+            left: self.camera_x / TILE_WIDTH,
+            top: self.camera_y / TILE_HEIGHT,
+            right: ((self.camera_x + self.screen_width - 1) / TILE_WIDTH).min(last_x),
+            bottom: ((self.camera_y + self.screen_height - 1) / TILE_HEIGHT).min(last_y),
+        }
+    }
+}
+
+// a tile of slack each way so a screen that isn't tile aligned never sees a tile it's still
+// writing, and a size that wraps with the 8 bit scroll
+fn check_ring_size(width: usize, height: usize, screen_width: usize, screen_height: usize) {
+    let (pixel_width, pixel_height) = (width * TILE_WIDTH, height * TILE_HEIGHT);
+
+    assert!(
+        pixel_width >= screen_width + TILE_WIDTH && pixel_height >= screen_height + TILE_HEIGHT,
+        "a {width}x{height} tile bg layer can't stream a {screen_width}x{screen_height} screen, \
+         it has to be a tile wider and taller than the screen"
+    );
+    assert!(
+        256 % pixel_width == 0 && 256 % pixel_height == 0,
+        "a {width}x{height} tile bg layer can't stream, its size in pixels has to divide 256"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world_tile(tile_x: usize, tile_y: usize) -> TileIndex {
+        TileIndex((tile_x * 3 + tile_y * 7) as u8)
+    }
+
+    // every screen pixel shows the world tile under the camera
+    fn check_screen(streamer: &TilemapStreamer, layer: &BgLayer) {
+        let (camera_x, camera_y) = streamer.camera();
+
+        for screen_y in 0..SCREEN_HEIGHT {
+            for screen_x in 0..SCREEN_WIDTH {
+                let map_x = (screen_x as u8).wrapping_sub(layer.x) as usize;
+                let map_y = (screen_y as u8).wrapping_sub(layer.y) as usize;
+                let map_index = layer.get_map_index(map_x / TILE_WIDTH, map_y / TILE_HEIGHT);
+
+                let world_x = (camera_x + screen_x) / TILE_WIDTH;
+                let world_y = (camera_y + screen_y) / TILE_HEIGHT;
+
+                assert_eq!(
+                    layer.tiles[map_index],
+                    world_tile(world_x, world_y),
+                    "camera ({camera_x}, {camera_y}), screen ({screen_x}, {screen_y})"
+                );
+                assert_eq!(
+                    *layer.attributes[map_index].get_palette().get(),
+                    (world_y % 8) as u8
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn scroll_across_world() {
+        let mut world = BgLayer::new(256, 64);
+        for tile_y in 0..64 {
+            for tile_x in 0..256 {
+                let index = world.get_map_index(tile_x, tile_y);
+                world.tiles[index] = world_tile(tile_x, tile_y);
+                world.attributes[index] =
+                    TileAttributes::default().with_palette(Subpalette::new((tile_y % 8) as u8));
+            }
+        }
+
+        let mut streamer = TilemapStreamer::new(world, &VfcConfig::default());
+        let mut layer = BgLayer::new(BG_WIDTH, BG_HEIGHT);
+
+        // the first update fills the screen, 24x20 tiles
+        assert_eq!(streamer.update(&mut layer), 24 * 20);
+        check_screen(&streamer, &layer);
+        assert_eq!(streamer.update(&mut layer), 0);
+
+        // a pixel to the right exposes one column, the next seven pixels nothing new
+        streamer.set_camera(1, 0);
+        assert_eq!(streamer.update(&mut layer), 20);
+        streamer.set_camera(7, 0);
+        assert_eq!(streamer.update(&mut layer), 0);
+        // and a few pixels down, one row
+        streamer.set_camera(8, 3);
+        assert_eq!(streamer.update(&mut layer), 24);
+        check_screen(&streamer, &layer);
+
+        // right across the whole world, then down and back, at odd speeds
+        while streamer.camera().0 < 256 * TILE_WIDTH - SCREEN_WIDTH {
+            let (x, y) = streamer.camera();
+            streamer.set_camera(x + 13, y + 1);
+            streamer.update(&mut layer);
+            check_screen(&streamer, &layer);
+        }
+        assert_eq!(streamer.camera().0, 256 * TILE_WIDTH - SCREEN_WIDTH);
+
+        while streamer.camera().0 > 0 {
+            let (x, y) = streamer.camera();
+            streamer.set_camera(x.saturating_sub(29), y + 5);
+            streamer.update(&mut layer);
+            check_screen(&streamer, &layer);
+        }
+        assert_eq!(streamer.camera(), (0, 64 * TILE_HEIGHT - SCREEN_HEIGHT));
+
+        // a jump further than the ring buffer is a full redraw
+        streamer.follow(1000, 100);
+        streamer.update(&mut layer);
+        check_screen(&streamer, &layer);
+    }
+}
@@ -0,0 +1,905 @@
+// maps made in the tiled editor (https://www.mapeditor.org), from json (.tmj/.json) or tmx.
+//
+// tile ids are counted across every tileset of the map, so tile index n is gid n + 1 no
+// matter which tileset it's in. import the tilesets' sheets one after another into the same
+// bank and the indices line up. tilesets have to be embedded in the map, and tile layers
+// have to be csv or xml encoded
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde_json::Value;
+
+use vfc::*;
+
+const FLIPPED_HORIZONTALLY: u32 = 0x8000_0000;
+const FLIPPED_VERTICALLY: u32 = 0x4000_0000;
+const FLIPPED_DIAGONALLY: u32 = 0x2000_0000;
+const GID_MASK: u32 = 0x0fff_ffff;
+
+#[derive(Debug)]
+pub enum TiledError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Xml(xml::reader::Error),
+    // a field the map needs isn't there or isn't the right type
+    Missing(&'static str),
+    // base64 or compressed layer data, or an infinite map
+    Unsupported(String),
+    // a tileset in its own file
+    ExternalTileset(String),
+    // a gid past the last tile of a bank
+    TileOutOfRange(u32),
+    // a tile layer whose data doesn't match its size
+    BadLayerSize(String),
+    // a tile layer bigger than a bg layer can be
+    LayerTooBig(String),
+}
+
+impl std::fmt::Display for TiledError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TiledError::Io(e) => write!(f, "{e}"),
+            TiledError::Json(e) => write!(f, "{e}"),
+            TiledError::Xml(e) => write!(f, "{e}"),
+            TiledError::Missing(field) => write!(f, "missing or invalid {field}"),
+            TiledError::Unsupported(what) => write!(f, "{what} isn't supported"),
+            TiledError::ExternalTileset(source) => write!(
+                f,
+                "tileset {source} is in its own file, embed it in the map instead"
+            ),
+            TiledError::TileOutOfRange(gid) => {
+                write!(f, "gid {gid} is past the {TILES_PER_BANK} tiles of a bank")
+            }
+            TiledError::BadLayerSize(name) => {
+                write!(f, "tile layer {name} has the wrong number of tiles")
+            }
+            TiledError::LayerTooBig(name) => write!(
+                f,
+                "tile layer {name} is bigger than {MAX_BG_SIZE}x{MAX_BG_SIZE} tiles, \
+                 copy it in parts with write_bg_layer or stream it with TilemapStreamer"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TiledError {}
+
+impl From<std::io::Error> for TiledError {
+    fn from(e: std::io::Error) -> TiledError {
+        TiledError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for TiledError {
+    fn from(e: serde_json::Error) -> TiledError {
+        TiledError::Json(e)
+    }
+}
+
+impl From<xml::reader::Error> for TiledError {
+    fn from(e: xml::reader::Error) -> TiledError {
+        TiledError::Xml(e)
+    }
+}
+
+// a custom property. colors and files are kept as strings, object references as ints
+#[derive(Debug, Clone, PartialEq)]
+pub enum PropertyValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+}
+
+impl PropertyValue {
+    // true bools and nonzero numbers, for flags
+    pub fn is_set(&self) -> bool {
+        match self {
+            PropertyValue::Bool(b) => *b,
+            PropertyValue::Int(i) => *i != 0,
+            PropertyValue::Float(f) => *f != 0.0,
+            PropertyValue::String(_) => false,
+        }
+    }
+}
+
+pub type Properties = HashMap<String, PropertyValue>;
+
+// a placed tile, with tiled's flip flags as a TileAttributes rotation
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct MapTile {
+    pub tile_index: TileIndex,
+    pub rotation: u8,
+}
+
+impl MapTile {
+    // None for gid 0, which is no tile at all
+    pub fn from_gid(gid: u32) -> Result<Option<MapTile>, TiledError> {
+        let id = gid & GID_MASK;
+
+        if id == 0 {
+            return Ok(None);
+        }
+
+        if id as usize > TILES_PER_BANK {
+            return Err(TiledError::TileOutOfRange(id));
+        }
+
+        // tiled flips along the diagonal first, then horizontally and vertically, the same
+        // order as the vfc
+        let mut rotation = 0;
+        if gid & FLIPPED_HORIZONTALLY != 0 {
+            rotation |= 0b001;
+        }
+        if gid & FLIPPED_VERTICALLY != 0 {
+            rotation |= 0b010;
+        }
+        if gid & FLIPPED_DIAGONALLY != 0 {
+            rotation |= 0b100;
+        }
+
+        Ok(Some(MapTile {
+            tile_index: TileIndex((id - 1) as u8),
+            rotation,
+        }))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TileLayer {
+    pub name: String,
+    // in tiles
+    pub width: usize,
+    pub height: usize,
+    // row by row, None where there's no tile
+    pub tiles: Vec<Option<MapTile>>,
+    pub properties: Properties,
+}
+
+// something placed on an object layer. positions are in pixels from the top left of the map
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapObject {
+    pub id: u32,
+    pub name: String,
+    // the object's class, called its type before tiled 1.9
+    pub kind: String,
+    // which object layer it was on
+    pub layer: String,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    // tile objects have a tile, the rest are shapes or points
+    pub tile: Option<MapTile>,
+    pub properties: Properties,
+}
+
+#[derive(Debug, Clone)]
+pub struct TiledMap {
+    // in tiles
+    pub width: usize,
+    pub height: usize,
+    pub tile_layers: Vec<TileLayer>,
+    // every object of every object layer, in map order
+    pub objects: Vec<MapObject>,
+    // the custom properties of each tile that has any
+    pub tile_properties: HashMap<u8, Properties>,
+    pub properties: Properties,
+}
+
+impl TiledMap {
+    // tmx unless the file ends in .json or .tmj
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<TiledMap, TiledError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") | Some("tmj") => TiledMap::from_json(&text),
+            _ => TiledMap::from_tmx(&text),
+        }
+    }
+
+    pub fn from_json(text: &str) -> Result<TiledMap, TiledError> {
+        let map: Value = serde_json::from_str(text)?;
+
+        if map["infinite"].as_bool() == Some(true) {
+            return Err(TiledError::Unsupported("an infinite map".to_string()));
+        }
+
+        check_tile_size(json_u32(&map, "tilewidth")?, json_u32(&map, "tileheight")?)?;
+
+        let mut tiled_map = TiledMap {
+            width: json_usize(&map, "width")?,
+            height: json_usize(&map, "height")?,
+            tile_layers: vec![],
+            objects: vec![],
+            tile_properties: HashMap::new(),
+            properties: json_properties(&map)?,
+        };
+
+        for tileset in json_array(&map, "tilesets")? {
+            if let Some(source) = tileset["source"].as_str() {
+                return Err(TiledError::ExternalTileset(source.to_string()));
+            }
+
+            let first_gid = json_u32(tileset, "firstgid")?;
+
+            for tile in tileset["tiles"].as_array().into_iter().flatten() {
+                let gid = tile_gid(first_gid, json_u32(tile, "id")?)?;
+                tiled_map.add_tile_properties(gid, json_properties(tile)?)?;
+            }
+        }
+
+        tiled_map.add_json_layers(json_array(&map, "layers")?)?;
+
+        Ok(tiled_map)
+    }
+
+    pub fn from_tmx(text: &str) -> Result<TiledMap, TiledError> {
+        let map = Element::parse(text)?;
+
+        if map.name != "map" {
+            return Err(TiledError::Missing("map"));
+        }
+
+        if map.attribute("infinite") == Some("1") {
+            return Err(TiledError::Unsupported("an infinite map".to_string()));
+        }
+
+        check_tile_size(
+            map.parse_attribute("tilewidth")?,
+            map.parse_attribute("tileheight")?,
+        )?;
+
+        let mut tiled_map = TiledMap {
+            width: map.parse_attribute("width")?,
+            height: map.parse_attribute("height")?,
+            tile_layers: vec![],
+            objects: vec![],
+            tile_properties: HashMap::new(),
+            properties: map.properties()?,
+        };
+
+        for tileset in map.children_named("tileset") {
+            if let Some(source) = tileset.attribute("source") {
+                return Err(TiledError::ExternalTileset(source.to_string()));
+            }
+
+            let first_gid: u32 = tileset.parse_attribute("firstgid")?;
+
+            for tile in tileset.children_named("tile") {
+                let gid = tile_gid(first_gid, tile.parse_attribute("id")?)?;
+                tiled_map.add_tile_properties(gid, tile.properties()?)?;
+            }
+        }
+
+        tiled_map.add_tmx_layers(&map)?;
+
+        Ok(tiled_map)
+    }
+
+    pub fn tile_layer(&self, name: &str) -> Option<&TileLayer> {
+        self.tile_layers.iter().find(|layer| layer.name == name)
+    }
+
+    // the objects of one kind, e.g. every "enemy"
+    pub fn objects_of_kind<'a>(&'a self, kind: &'a str) -> impl Iterator<Item = &'a MapObject> {
+        self.objects
+            .iter()
+            .filter(move |object| object.kind == kind)
+    }
+
+    // a bg layer big enough for a tile layer, each side rounded up to a power of two like the
+    // hardware needs. each tile gets its subpalette from `subpalettes`, like the list
+    // import_tileset returns, or the first one if the list is too short
+    pub fn bg_layer(
+        &self,
+        layer: &TileLayer,
+        subpalettes: &[Subpalette],
+    ) -> Result<BgLayer, TiledError> {
+        let width = layer.width.next_power_of_two();
+        let height = layer.height.next_power_of_two();
+
+        if width > MAX_BG_SIZE || height > MAX_BG_SIZE {
+            return Err(TiledError::LayerTooBig(layer.name.clone()));
+        }
+
+        let mut bg_layer = BgLayer::new(width, height);
+        self.write_bg_layer(layer, subpalettes, &mut bg_layer, (0, 0));
+        Ok(bg_layer)
+    }
+
+    // copy a tile layer into an existing bg layer with its top left at `(tile_x, tile_y)`,
+    // wrapping around the edges of the bg layer. empty tiles become tile 0
+    pub fn write_bg_layer(
+        &self,
+        layer: &TileLayer,
+        subpalettes: &[Subpalette],
+        bg_layer: &mut BgLayer,
+        (tile_x, tile_y): (usize, usize),
+    ) {
+        for y in 0..layer.height {
+            for x in 0..layer.width {
+                let map_tile = layer.tiles[y * layer.width + x].unwrap_or_default();
+                let map_index = bg_layer.get_map_index(tile_x + x, tile_y + y);
+
+                let subpalette = subpalettes
+                    .get(map_tile.tile_index.0 as usize)
+                    .copied()
+                    .unwrap_or_default();
+
+                bg_layer.tiles[map_index] = map_tile.tile_index;
+                bg_layer.attributes[map_index] = TileAttributes::default()
+                    .with_palette(subpalette)
+                    .with_rotation(map_tile.rotation);
+            }
+        }
+    }
+
+    // one byte per tile of a layer, with bit n set when the tile's custom property called
+    // `flags[n]` is true. so ["solid", "ladder"] makes solid tiles 0b01 and ladders 0b10
+    pub fn collision_flags(&self, layer: &TileLayer, flags: &[&str]) -> Vec<u8> {
+        layer
+            .tiles
+            .iter()
+            .map(|map_tile| {
+                let Some(properties) =
+                    map_tile.and_then(|map_tile| self.tile_properties.get(&map_tile.tile_index.0))
+                else {
+                    return 0;
+                };
+
+                flags
+                    .iter()
+                    .take(8)
+                    .enumerate()
+                    .filter(|(_, flag)| properties.get(**flag).is_some_and(|p| p.is_set()))
+                    .fold(0, |bits, (bit, _)| bits | 1 << bit)
+            })
+            .collect()
+    }
+
+    fn add_tile_properties(&mut self, gid: u32, properties: Properties) -> Result<(), TiledError> {
+        if properties.is_empty() {
+            return Ok(());
+        }
+
+        if let Some(map_tile) = MapTile::from_gid(gid)? {
+            self.tile_properties
+                .insert(map_tile.tile_index.0, properties);
+        }
+
+        Ok(())
+    }
+
+    // groups are flattened, their layers are added in order
+    fn add_json_layers(&mut self, layers: &[Value]) -> Result<(), TiledError> {
+        for layer in layers {
+            let name = layer["name"].as_str().unwrap_or_default().to_string();
+
+            match layer["type"].as_str() {
+                Some("tilelayer") => {
+                    if layer["chunks"].is_array() {
+                        return Err(TiledError::Unsupported("an infinite map".to_string()));
+                    }
+
+                    let gids = match &layer["data"] {
+                        Value::Array(gids) => gids
+                            .iter()
+                            .map(|gid| gid.as_u64().map(|gid| gid as u32))
+                            .collect::<Option<Vec<_>>>()
+                            .ok_or(TiledError::Missing("layer data"))?,
+                        _ => {
+                            return Err(TiledError::Unsupported(format!(
+                                "{} layer data",
+                                layer["encoding"].as_str().unwrap_or("encoded")
+                            )))
+                        }
+                    };
+
+                    self.tile_layers.push(tile_layer(
+                        name,
+                        json_usize(layer, "width")?,
+                        json_usize(layer, "height")?,
+                        &gids,
+                        json_properties(layer)?,
+                    )?);
+                }
+                Some("objectgroup") => {
+                    for object in json_array(layer, "objects")? {
+                        let kind = object["class"]
+                            .as_str()
+                            .or(object["type"].as_str())
+                            .unwrap_or_default();
+
+                        let tile = match object["gid"].as_u64() {
+                            Some(gid) => MapTile::from_gid(gid as u32)?,
+                            None => None,
+                        };
+
+                        self.objects.push(MapObject {
+                            id: json_u32(object, "id")?,
+                            name: object["name"].as_str().unwrap_or_default().to_string(),
+                            kind: kind.to_string(),
+                            layer: name.clone(),
+                            x: json_f32(object, "x"),
+                            y: json_f32(object, "y"),
+                            width: json_f32(object, "width"),
+                            height: json_f32(object, "height"),
+                            tile,
+                            properties: json_properties(object)?,
+                        });
+                    }
+                }
+                Some("group") => self.add_json_layers(json_array(layer, "layers")?)?,
+                // image layers
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn add_tmx_layers(&mut self, parent: &Element) -> Result<(), TiledError> {
+        for layer in &parent.children {
+            let name = layer.attribute("name").unwrap_or_default().to_string();
+
+            match layer.name.as_str() {
+                "layer" => {
+                    let data = layer
+                        .children_named("data")
+                        .next()
+                        .ok_or(TiledError::Missing("layer data"))?;
+
+                    let gids = match data.attribute("encoding") {
+                        Some("csv") => data
+                            .text
+                            .split(',')
+                            .map(|gid| gid.trim().parse())
+                            .collect::<Result<Vec<u32>, _>>()
+                            .map_err(|_| TiledError::Missing("layer data"))?,
+                        None if data.children_named("chunk").next().is_some() => {
+                            return Err(TiledError::Unsupported("an infinite map".to_string()))
+                        }
+                        None => data
+                            .children_named("tile")
+                            // empty tiles are written without a gid
+                            .map(|tile| match tile.attribute("gid") {
+                                Some(_) => tile.parse_attribute("gid"),
+                                None => Ok(0),
+                            })
+                            .collect::<Result<Vec<u32>, TiledError>>()?,
+                        Some(encoding) => {
+                            return Err(TiledError::Unsupported(format!("{encoding} layer data")))
+                        }
+                    };
+
+                    self.tile_layers.push(tile_layer(
+                        name,
+                        layer.parse_attribute("width")?,
+                        layer.parse_attribute("height")?,
+                        &gids,
+                        layer.properties()?,
+                    )?);
+                }
+                "objectgroup" => {
+                    for object in layer.children_named("object") {
+                        let kind = object
+                            .attribute("class")
+                            .or(object.attribute("type"))
+                            .unwrap_or_default();
+
+                        let tile = match object.attribute("gid") {
+                            Some(_) => MapTile::from_gid(object.parse_attribute("gid")?)?,
+                            None => None,
+                        };
+
+                        self.objects.push(MapObject {
+                            id: object.parse_attribute("id")?,
+                            name: object.attribute("name").unwrap_or_default().to_string(),
+                            kind: kind.to_string(),
+                            layer: name.clone(),
+                            x: object.parse_attribute("x").unwrap_or(0.0),
+                            y: object.parse_attribute("y").unwrap_or(0.0),
+                            width: object.parse_attribute("width").unwrap_or(0.0),
+                            height: object.parse_attribute("height").unwrap_or(0.0),
+                            tile,
+                            properties: object.properties()?,
+                        });
+                    }
+                }
+                "group" => self.add_tmx_layers(layer)?,
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn tile_layer(
+    name: String,
+    width: usize,
+    height: usize,
+    gids: &[u32],
+    properties: Properties,
+) -> Result<TileLayer, TiledError> {
+    if gids.len() != width * height {
+        return Err(TiledError::BadLayerSize(name));
+    }
+
+    Ok(TileLayer {
+        name,
+        width,
+        height,
+        tiles: gids
+            .iter()
+            .map(|&gid| MapTile::from_gid(gid))
+            .collect::<Result<_, _>>()?,
+        properties,
+    })
+}
+
+fn property_value(kind: &str, value: &str) -> Option<PropertyValue> {
+    match kind {
+        "bool" => value.parse().ok().map(PropertyValue::Bool),
+        "int" | "object" => value.parse().ok().map(PropertyValue::Int),
+        "float" => value.parse().ok().map(PropertyValue::Float),
+        // string, color, file
+        _ => Some(PropertyValue::String(value.to_string())),
+    }
+}
+
+// json
+
+fn json_array<'a>(value: &'a Value, field: &'static str) -> Result<&'a [Value], TiledError> {
+    value[field]
+        .as_array()
+        .map(|array| array.as_slice())
+        .ok_or(TiledError::Missing(field))
+}
+
+fn json_usize(value: &Value, field: &'static str) -> Result<usize, TiledError> {
+    value[field]
+        .as_u64()
+        .map(|n| n as usize)
+        .ok_or(TiledError::Missing(field))
+}
+
+// tiles on the vfc are always 8x8
+fn check_tile_size(width: u32, height: u32) -> Result<(), TiledError> {
+    if (width, height) != (8, 8) {
+        return Err(TiledError::Unsupported(format!("{width}x{height} tiles")));
+    }
+
+    Ok(())
+}
+
+fn tile_gid(first_gid: u32, id: u32) -> Result<u32, TiledError> {
+    first_gid
+        .checked_add(id)
+        .ok_or(TiledError::TileOutOfRange(id))
+}
+
+fn json_u32(value: &Value, field: &'static str) -> Result<u32, TiledError> {
+    value[field]
+        .as_u64()
+        .map(|n| n as u32)
+        .ok_or(TiledError::Missing(field))
+}
+
+fn json_f32(value: &Value, field: &'static str) -> f32 {
+    value[field].as_f64().unwrap_or_default() as f32
+}
+
+fn json_properties(value: &Value) -> Result<Properties, TiledError> {
+    let mut properties = Properties::new();
+
+    for property in value["properties"].as_array().into_iter().flatten() {
+        let name = property["name"]
+            .as_str()
+            .ok_or(TiledError::Missing("property name"))?;
+
+        let value = match (
+            property["type"].as_str().unwrap_or("string"),
+            &property["value"],
+        ) {
+            (_, Value::Bool(b)) => Some(PropertyValue::Bool(*b)),
+            ("int" | "object", value) => value.as_i64().map(PropertyValue::Int),
+            ("float", value) => value.as_f64().map(PropertyValue::Float),
+            (_, Value::String(s)) => Some(PropertyValue::String(s.clone())),
+            // class properties
+            _ => None,
+        };
+
+        if let Some(value) = value {
+            properties.insert(name.to_string(), value);
+        }
+    }
+
+    Ok(properties)
+}
+
+// tmx, read into a tree of elements first
+
+#[derive(Debug, Default)]
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Element>,
+    text: String,
+}
+
+impl Element {
+    // the root element
+    fn parse(text: &str) -> Result<Element, TiledError> {
+        use xml::reader::{EventReader, XmlEvent};
+
+        let mut stack: Vec<Element> = vec![];
+
+        for event in EventReader::from_str(text) {
+            match event? {
+                XmlEvent::StartElement {
+                    name, attributes, ..
+                } => stack.push(Element {
+                    name: name.local_name,
+                    attributes: attributes
+                        .into_iter()
+                        .map(|attribute| (attribute.name.local_name, attribute.value))
+                        .collect(),
+                    ..Element::default()
+                }),
+                XmlEvent::Characters(text) | XmlEvent::CData(text) => {
+                    if let Some(element) = stack.last_mut() {
+                        element.text.push_str(&text);
+                    }
+                }
+                XmlEvent::EndElement { .. } => {
+                    let element = stack.pop().ok_or(TiledError::Missing("map"))?;
+
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(element),
+                        None => return Ok(element),
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        Err(TiledError::Missing("map"))
+    }
+
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(attribute, _)| attribute == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn parse_attribute<T: std::str::FromStr>(&self, name: &'static str) -> Result<T, TiledError> {
+        self.attribute(name)
+            .and_then(|value| value.parse().ok())
+            .ok_or(TiledError::Missing(name))
+    }
+
+    fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+
+    fn properties(&self) -> Result<Properties, TiledError> {
+        let mut properties = Properties::new();
+
+        for property in self
+            .children_named("properties")
+            .flat_map(|properties| properties.children_named("property"))
+        {
+            let name = property
+                .attribute("name")
+                .ok_or(TiledError::Missing("property name"))?;
+
+            // long strings are kept in the element instead of the attribute
+            let value = property.attribute("value").unwrap_or(&property.text);
+            let kind = property.attribute("type").unwrap_or("string");
+
+            if kind == "class" {
+                continue;
+            }
+
+            if let Some(value) = property_value(kind, value) {
+                properties.insert(name.to_string(), value);
+            }
+        }
+
+        Ok(properties)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the same 4x2 map both ways: tile 2 flipped horizontally at (1, 0), solid tile 3 at
+    // (2, 1), and a player and an enemy on an object layer
+    const MAP_JSON: &str = r#"{
+        "width": 4, "height": 2, "tilewidth": 8, "tileheight": 8, "infinite": false,
+        "layers": [
+            {"type": "tilelayer", "name": "ground", "width": 4, "height": 2,
+             "data": [1, 2147483651, 0, 1, 1, 1, 4, 1]},
+            {"type": "group", "name": "things", "layers": [
+                {"type": "objectgroup", "name": "spawns", "objects": [
+                    {"id": 1, "name": "player", "type": "spawn", "x": 8, "y": 4.5,
+                     "width": 0, "height": 0},
+                    {"id": 2, "name": "", "class": "enemy", "x": 24, "y": 8,
+                     "width": 8, "height": 8, "gid": 1073741829,
+                     "properties": [{"name": "hp", "type": "int", "value": 3}]}
+                ]}
+            ]}
+        ],
+        "tilesets": [
+            {"firstgid": 1, "name": "tiles", "tiles": [
+                {"id": 3, "properties": [
+                    {"name": "solid", "type": "bool", "value": true},
+                    {"name": "ladder", "type": "bool", "value": false}
+                ]},
+                {"id": 4, "properties": [{"name": "ladder", "type": "int", "value": 1}]}
+            ]}
+        ]
+    }"#;
+
+    const MAP_TMX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <map version="1.10" orientation="orthogonal" width="4" height="2"
+             tilewidth="8" tileheight="8" infinite="0">
+         <tileset firstgid="1" name="tiles" tilewidth="8" tileheight="8">
+          <tile id="3">
+           <properties>
+            <property name="solid" type="bool" value="true"/>
+            <property name="ladder" type="bool" value="false"/>
+           </properties>
+          </tile>
+          <tile id="4">
+           <properties><property name="ladder" type="int" value="1"/></properties>
+          </tile>
+         </tileset>
+         <layer id="1" name="ground" width="4" height="2">
+          <data encoding="csv">
+        1,2147483651,0,1,
+        1,1,4,1
+        </data>
+         </layer>
+         <group name="things">
+          <objectgroup name="spawns">
+           <object id="1" name="player" type="spawn" x="8" y="4.5"/>
+           <object id="2" class="enemy" gid="1073741829" x="24" y="8" width="8" height="8">
+            <properties><property name="hp" type="int" value="3"/></properties>
+           </object>
+          </objectgroup>
+         </group>
+        </map>"#;
+
+    fn check_map(map: &TiledMap) {
+        assert_eq!((map.width, map.height), (4, 2));
+
+        let layer = map.tile_layer("ground").unwrap();
+        assert_eq!(
+            layer.tiles[1],
+            Some(MapTile {
+                tile_index: TileIndex(2),
+                rotation: 0b001
+            })
+        );
+        assert_eq!(layer.tiles[2], None);
+
+        let subpalettes = [Subpalette::new(0), Subpalette::new(0), Subpalette::new(5)];
+        let bg_layer = map.bg_layer(layer, &subpalettes).unwrap();
+        let attributes = bg_layer.attributes[bg_layer.get_map_index(1, 0)];
+        assert_eq!(bg_layer.tiles[bg_layer.get_map_index(1, 0)], TileIndex(2));
+        assert!(attributes.get_flip_x() && !attributes.get_flip_y());
+        assert_eq!(*attributes.get_palette().get(), 5);
+        assert_eq!(bg_layer.tiles[bg_layer.get_map_index(2, 0)], TileIndex(0));
+
+        assert_eq!(
+            map.collision_flags(layer, &["solid", "ladder"]),
+            [0, 0, 0, 0, 0, 0, 0b01, 0]
+        );
+
+        assert_eq!(map.objects.len(), 2);
+        let player = map.objects_of_kind("spawn").next().unwrap();
+        assert_eq!(player.name, "player");
+        assert_eq!(player.layer, "spawns");
+        assert_eq!((player.x, player.y), (8.0, 4.5));
+        assert_eq!(player.tile, None);
+
+        let enemy = map.objects_of_kind("enemy").next().unwrap();
+        assert_eq!(enemy.properties["hp"], PropertyValue::Int(3));
+        assert_eq!(
+            enemy.tile,
+            Some(MapTile {
+                tile_index: TileIndex(4),
+                rotation: 0b010
+            })
+        );
+    }
+
+    #[test]
+    fn json_map() {
+        check_map(&TiledMap::from_json(MAP_JSON).unwrap());
+    }
+
+    #[test]
+    fn tmx_map() {
+        check_map(&TiledMap::from_tmx(MAP_TMX).unwrap());
+    }
+
+    #[test]
+    fn unsupported_maps() {
+        let external = MAP_TMX.replace(r#"name="tiles""#, r#"source="tiles.tsx""#);
+        assert!(matches!(
+            TiledMap::from_tmx(&external),
+            Err(TiledError::ExternalTileset(_))
+        ));
+
+        let base64 = MAP_TMX.replace(r#"encoding="csv""#, r#"encoding="base64""#);
+        assert!(matches!(
+            TiledMap::from_tmx(&base64),
+            Err(TiledError::Unsupported(_))
+        ));
+
+        let short = MAP_JSON.replace("4, 1]", "4]");
+        assert!(matches!(
+            TiledMap::from_json(&short),
+            Err(TiledError::BadLayerSize(_))
+        ));
+
+        // bg layers only come in powers of two up to MAX_BG_SIZE
+        let map = TiledMap::from_json(MAP_JSON).unwrap();
+        let mut layer = map.tile_layer("ground").unwrap().clone();
+        layer.width = 20;
+        layer.height = 3;
+        layer.tiles = vec![None; 20 * 3];
+        let bg_layer = map.bg_layer(&layer, &[]).unwrap();
+        assert_eq!((bg_layer.width(), bg_layer.height()), (32, 4));
+
+        layer.width = MAX_BG_SIZE + 1;
+        layer.tiles = vec![None; (MAX_BG_SIZE + 1) * 3];
+        assert!(matches!(
+            map.bg_layer(&layer, &[]),
+            Err(TiledError::LayerTooBig(_))
+        ));
+
+        let big_tiles = MAP_JSON.replace(r#""tilewidth": 8"#, r#""tilewidth": 16"#);
+        assert!(matches!(
+            TiledMap::from_json(&big_tiles),
+            Err(TiledError::Unsupported(_))
+        ));
+
+        let tall_tiles = MAP_TMX.replacen(r#"tileheight="8""#, r#"tileheight="16""#, 1);
+        assert!(matches!(
+            TiledMap::from_tmx(&tall_tiles),
+            Err(TiledError::Unsupported(_))
+        ));
+
+        let overflow = MAP_TMX.replace(r#"firstgid="1""#, r#"firstgid="4294967295""#);
+        assert!(matches!(
+            TiledMap::from_tmx(&overflow),
+            Err(TiledError::TileOutOfRange(_))
+        ));
+
+        // xml layer data: a tile without a gid is empty, one with a bad gid is an error
+        let csv = "1,2147483651,0,1,\n        1,1,4,1";
+        let xml = MAP_TMX.replace(r#"<data encoding="csv">"#, "<data>");
+        let tiles = r#"<tile gid="1"/><tile gid="2147483651"/><tile/><tile gid="1"/>
+            <tile gid="1"/><tile gid="1"/><tile gid="4"/><tile gid="1"/>"#;
+        check_map(&TiledMap::from_tmx(&xml.replace(csv, tiles)).unwrap());
+        assert!(matches!(
+            TiledMap::from_tmx(&xml.replace(csv, &tiles.replace("\"4\"", "\"four\""))),
+            Err(TiledError::Missing("gid"))
+        ));
+
+        let big_gid = MAP_JSON.replace("[1, 2147483651", "[300, 2147483651");
+        assert!(matches!(
+            TiledMap::from_json(&big_gid),
+            Err(TiledError::TileOutOfRange(300))
+        ));
+    }
+}
@@ -25,7 +25,7 @@ const TILE_MAP_DOT: usize = 0x80;
 const MAP_X: usize = 9;
 const MAP_Y: usize = 11;
 
-fn render_to_argb_u32(framebuffer: &[vfc::Rgb; vfc::NUM_SCREEN_PIXELS], target_buffer: &mut [u32]) {
+fn render_to_argb_u32(framebuffer: &[vfc::Rgb], target_buffer: &mut [u32]) {
     for (index, argb) in framebuffer.iter().map(|rgb| rgb.as_argb_u32()).enumerate() {
         target_buffer[index] = argb;
     }
@@ -403,19 +403,10 @@ fn main() {
         Rgb::new(0xee, 0xee, 0xdd), // White
     ];
 
-    fc.palette = Palette::new(
-        (0..NUM_PALETTE_ENTRIES)
-            .map(|index| {
-                if index < (&preview_palette_array[..]).len() {
-                    preview_palette_array[index]
-                } else {
-                    Rgb::default()
-                }
-            })
-            .collect::<Vec<_>>()
-            .try_into()
-            .unwrap_or_else(|_| unreachable!()),
-    );
+    fc.palette = PaletteBuilder::new()
+        .with_colors(&preview_palette_array)
+        .unwrap()
+        .build();
 
     fc.tileset = load_tileset_from_path("hunt/hunt_tiles.png").unwrap();
 
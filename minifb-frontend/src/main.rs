@@ -1,5 +1,6 @@
 use minifb::{Key, KeyRepeat, Scale, Window, WindowOptions};
 
+use sayre::vfc::debug;
 use sayre::vfc::SCREEN_HEIGHT as HEIGHT;
 use sayre::vfc::SCREEN_WIDTH as WIDTH;
 
@@ -51,9 +52,18 @@ fn main() {
     let mut test_x: u8 = 80;
     let mut test_y: u8 = 40;
 
+    // F1 steps through the debug views, shown in a second window
+    let mut debug_view = 0;
+    let mut debug_window: Option<Window> = None;
+
     while window.is_open() && !window.is_key_down(Key::Escape) {
         //~ start_time = std::time::Instant::now();
 
+        if window.is_key_pressed(Key::F1, KeyRepeat::No) {
+            debug_view = (debug_view + 1) % 5;
+            debug_window = None;
+        }
+
         if window.is_key_pressed(Key::F8, KeyRepeat::No) {
             vfc.oam_hidden = !vfc.oam_hidden;
         }
@@ -117,7 +127,7 @@ fn main() {
                     //~ list.render_partial(4, start, 4, &mut vfc.oam);
                     list.clear();
                 }
-                None => {},
+                None => {}
             }
             let mut list = sayre::sprite::test_list(test_x, test_y);
             list.render(4, &mut vfc.oam);
@@ -136,6 +146,33 @@ fn main() {
             old_pixel = (*pixel).clone();
         }*/
 
+        if debug_view != 0 {
+            let image = match debug_view {
+                1 => debug::full_tileset_sheets(&vfc),
+                2 => debug::bg_layer_map(&vfc, 0),
+                3 => debug::bg_layer_map(&vfc, 1),
+                _ => debug::oam_grid(&vfc),
+            };
+
+            let debug_window = debug_window.get_or_insert_with(|| {
+                Window::new(
+                    "Debug - F1 for the next view",
+                    image.width,
+                    image.height,
+                    WindowOptions::default(),
+                )
+                .unwrap_or_else(|e| {
+                    panic!("{}", e);
+                })
+            });
+
+            let debug_buffer: Vec<u32> = image.pixels.iter().map(|rgb| rgb.as_argb_u32()).collect();
+
+            debug_window
+                .update_with_buffer(&debug_buffer, image.width, image.height)
+                .unwrap();
+        }
+
         // We unwrap here as we want this code to exit if it fails.
         // Real applications may want to handle this in a different way
         window.update_with_buffer(&buffer, WIDTH, HEIGHT).unwrap();
@@ -1,7 +1,6 @@
 use std::collections::HashMap;
 
 mod col;
-mod file;
 mod piece;
 mod random;
 mod tet;
@@ -9,9 +8,9 @@ mod tet;
 //~ use piece::Piece;
 use piece::FloatingPiece;
 
-use file::load_tileset_from_path;
+use hli::file::load_tileset_from_path;
 
-fn render_to_argb_u32(framebuffer: &[vfc::Rgb; vfc::NUM_SCREEN_PIXELS], target_buffer: &mut [u32]) {
+fn render_to_argb_u32(framebuffer: &[vfc::Rgb], target_buffer: &mut [u32]) {
     for (index, argb) in framebuffer.iter().map(|rgb| rgb.as_argb_u32()).enumerate() {
         target_buffer[index] = argb;
     }
@@ -195,19 +194,10 @@ fn main() {
                                     */
     ];
 
-    fc.palette = Palette::new(
-        (0..NUM_PALETTE_ENTRIES)
-            .map(|index| {
-                if index < (&preview_palette_array[..]).len() {
-                    preview_palette_array[index]
-                } else {
-                    Rgb::default()
-                }
-            })
-            .collect::<Vec<_>>()
-            .try_into()
-            .unwrap_or_else(|_| unreachable!()),
-    );
+    fc.palette = PaletteBuilder::new()
+        .with_colors(&preview_palette_array)
+        .unwrap()
+        .build();
 
     fc.tileset = load_tileset_from_path("quad_tiles.png").unwrap();
 
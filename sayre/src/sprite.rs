@@ -84,12 +84,7 @@ impl SpriteList {
 
                 let attributes = attributes[i].clone();
 
-                self.list.push(OamEntry {
-                    x,
-                    y,
-                    tile_index,
-                    attributes,
-                });
+                self.list.push(OamEntry::new(x, y, tile_index, attributes));
             }
         }
     }
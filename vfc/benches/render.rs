@@ -0,0 +1,70 @@
+// compares the renderers on a busy frame
+//
+//   cargo bench -p vfc
+//   cargo bench -p vfc --features parallel
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use vfc::*;
+
+// a full screen of scrolled tiles on both layers, with a line full of sprites every 16 lines
+fn busy_frame() -> Vfc {
+    let mut fc = Vfc::new();
+
+    for tile in 0..TILES_PER_BANK {
+        let rows = [[0, 1, 2, 3, 4, 5, 6, 7], [7, 6, 5, 4, 3, 2, 1, 0]];
+        let mut tile_rows = [[0; TILE_WIDTH]; TILE_HEIGHT];
+        for (y, row) in tile_rows.iter_mut().enumerate() {
+            *row = rows[(y + tile) % 2];
+        }
+        fc.tileset
+            .write_tile(TileIndex(tile as u8), testing::planar_tile(&tile_rows));
+    }
+
+    for (layer_index, layer) in fc.bg_layers.iter_mut().enumerate() {
+        for i in 0..layer.tiles.len() {
+            layer.tiles[i] = TileIndex((i * 7 + layer_index) as u8);
+            layer.attributes[i] = TileAttributes::default()
+                .with_palette(Subpalette::new(i as u8))
+                .with_rotation((i / 3) as u8);
+        }
+        layer.x = 5 + layer_index as u8 * 3;
+        layer.y = 9;
+    }
+
+    for i in 0..NUM_OAM_ENTRIES {
+        fc.oam.0[i] = OamEntry::new(
+            (i * 12) as u8,
+            (i / 16 * 16) as u8,
+            TileIndex(i as u8),
+            TileAttributes::default().with_rotation(i as u8),
+        )
+        .with_size(SpriteSize::Size16x16);
+    }
+
+    fc
+}
+
+fn render(c: &mut Criterion) {
+    let mut group = c.benchmark_group("render_frame");
+
+    let mut renderers = vec![("span", Renderer::Span), ("per_pixel", Renderer::PerPixel)];
+    #[cfg(feature = "parallel")]
+    renderers.push(("parallel", Renderer::Parallel));
+
+    for (name, renderer) in renderers {
+        let mut fc = busy_frame();
+        fc.renderer = renderer;
+
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                fc.render_frame();
+                black_box(&fc.framebuffer);
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, render);
+criterion_main!(benches);
@@ -0,0 +1,167 @@
+// affine (rotate/scale) background layers
+
+use crate::*;
+
+// 1.0 in the 8.8 fixed point used by the matrix
+pub const AFFINE_ONE: i32 = 256;
+
+pub const BG_PIXEL_WIDTH: usize = BG_WIDTH * TILE_WIDTH;
+pub const BG_PIXEL_HEIGHT: usize = BG_HEIGHT * TILE_HEIGHT;
+
+// what an affine layer shows outside of its map
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum AffineEdge {
+    // the map repeats forever
+    #[default]
+    Wrap,
+    // the pixels on the edge of the map are stretched outward
+    Clamp,
+    // nothing is drawn outside the map
+    Transparent,
+}
+
+// maps a screen pixel to a map pixel:
+//
+//   [map_x]   [a b]   [screen_x - screen_center_x]   [map_center_x]
+//   [map_y] = [c d] * [screen_y - screen_center_y] + [map_center_y]
+//
+// the matrix is 8.8 fixed point. while a layer has one of these, its `x` and `y` are ignored
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Affine {
+    pub a: i32,
+    pub b: i32,
+    pub c: i32,
+    pub d: i32,
+    pub screen_center_x: i32,
+    pub screen_center_y: i32,
+    pub map_center_x: i32,
+    pub map_center_y: i32,
+    pub edge: AffineEdge,
+}
+
+impl Default for Affine {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Affine {
+    pub fn identity() -> Affine {
+        Affine {
+            a: AFFINE_ONE,
+            b: 0,
+            c: 0,
+            d: AFFINE_ONE,
+            screen_center_x: 0,
+            screen_center_y: 0,
+            map_center_x: 0,
+            map_center_y: 0,
+            edge: AffineEdge::default(),
+        }
+    }
+
+    // show the map rotated by `angle` radians and zoomed by `scale`, with the map pixel
+    // (map_center_x, map_center_y) at the screen pixel (screen_center_x, screen_center_y)
+    pub fn rotate_scale(
+        angle: f64,
+        scale: f64,
+        screen_center: (i32, i32),
+        map_center: (i32, i32),
+    ) -> Affine {
+        // the matrix goes from screen to map, so it's the inverse of the transform we want to see
+        let (sin, cos) = (-angle).sin_cos();
+        let fixed = |n: f64| (n / scale * AFFINE_ONE as f64).round() as i32;
+
+        Affine {
+            a: fixed(cos),
+            b: fixed(-sin),
+            c: fixed(sin),
+            d: fixed(cos),
+            screen_center_x: screen_center.0,
+            screen_center_y: screen_center.1,
+            map_center_x: map_center.0,
+            map_center_y: map_center.1,
+            edge: AffineEdge::default(),
+        }
+    }
+
+    pub fn with_edge(mut self, edge: AffineEdge) -> Affine {
+        self.edge = edge;
+        self
+    }
+
+    // the map pixel under a screen pixel, or None if it falls outside a transparent-edged map.
+    // the map size is in pixels
+    pub fn map_pixel(
+        &self,
+        screen_x: u8,
+        screen_y: u8,
+        map_width: usize,
+        map_height: usize,
+    ) -> Option<(u8, u8)> {
+        let dx = screen_x as i32 - self.screen_center_x;
+        let dy = screen_y as i32 - self.screen_center_y;
+
+        let map_x = ((self.a * dx + self.b * dy) >> 8) + self.map_center_x;
+        let map_y = ((self.c * dx + self.d * dy) >> 8) + self.map_center_y;
+
+        let (width, height) = (map_width as i32, map_height as i32);
+
+        let (map_x, map_y) = match self.edge {
+            AffineEdge::Wrap => (map_x.rem_euclid(width), map_y.rem_euclid(height)),
+            AffineEdge::Clamp => (map_x.clamp(0, width - 1), map_y.clamp(0, height - 1)),
+            AffineEdge::Transparent => {
+                if map_x < 0 || map_x >= width || map_y < 0 || map_y >= height {
+                    return None;
+                }
+                (map_x, map_y)
+            }
+        };
+
+        Some((map_x as u8, map_y as u8))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotate_scale_matrix() {
+        let quarter_turn = Affine::rotate_scale(std::f64::consts::FRAC_PI_2, 1.0, (0, 0), (0, 0));
+        assert_eq!(
+            (
+                quarter_turn.a,
+                quarter_turn.b,
+                quarter_turn.c,
+                quarter_turn.d
+            ),
+            (0, AFFINE_ONE, -AFFINE_ONE, 0)
+        );
+
+        let zoom = Affine::rotate_scale(0.0, 2.0, (0, 0), (0, 0));
+        assert_eq!((zoom.a, zoom.d), (AFFINE_ONE / 2, AFFINE_ONE / 2));
+    }
+
+    #[test]
+    fn edges() {
+        let (w, h) = (BG_PIXEL_WIDTH, BG_PIXEL_HEIGHT);
+        let shifted = Affine {
+            map_center_x: -10,
+            map_center_y: 250,
+            ..Affine::identity()
+        };
+
+        assert_eq!(shifted.map_pixel(0, 0, w, h), Some((246, 250)));
+        assert_eq!(shifted.map_pixel(20, 10, w, h), Some((10, 4)));
+
+        let clamped = shifted.with_edge(AffineEdge::Clamp);
+        assert_eq!(clamped.map_pixel(0, 0, w, h), Some((0, 250)));
+        assert_eq!(clamped.map_pixel(20, 10, w, h), Some((10, 255)));
+
+        let transparent = shifted.with_edge(AffineEdge::Transparent);
+        assert_eq!(transparent.map_pixel(0, 0, w, h), None);
+        assert_eq!(transparent.map_pixel(20, 5, w, h), Some((10, 255)));
+        assert_eq!(transparent.map_pixel(20, 10, w, h), None);
+    }
+}
@@ -0,0 +1,49 @@
+// color math, for translucent layers and sprites
+
+use crate::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlendMode {
+    // brighten what's below, good for glows and light
+    Add,
+    // darken what's below, good for shadows
+    Subtract,
+    // half and half, good for glass, fog and ghosts
+    Average,
+}
+
+impl BlendMode {
+    // the byte the bus and save states use. 0 is no blending
+    pub fn to_byte(blend: Option<BlendMode>) -> u8 {
+        match blend {
+            None => 0,
+            Some(BlendMode::Add) => 1,
+            Some(BlendMode::Subtract) => 2,
+            Some(BlendMode::Average) => 3,
+        }
+    }
+
+    // None for 0, or any other byte that isn't a blend mode
+    pub fn from_byte(value: u8) -> Option<BlendMode> {
+        match value {
+            1 => Some(BlendMode::Add),
+            2 => Some(BlendMode::Subtract),
+            3 => Some(BlendMode::Average),
+            _ => None,
+        }
+    }
+
+    pub fn blend(&self, top: Rgb, below: Rgb) -> Rgb {
+        let mix = |top: u8, below: u8| match self {
+            BlendMode::Add => below.saturating_add(top),
+            BlendMode::Subtract => below.saturating_sub(top),
+            BlendMode::Average => ((top as u16 + below as u16) / 2) as u8,
+        };
+
+        Rgb::new(
+            mix(top.r(), below.r()),
+            mix(top.g(), below.g()),
+            mix(top.b(), below.b()),
+        )
+    }
+}
@@ -0,0 +1,430 @@
+// memory-mapped registers: the whole Vfc as a 16-bit address space of bytes
+//
+// every region is sized for the largest config, so addresses don't move between configs.
+// the parts a smaller config doesn't have read as 0 and ignore writes, and so does
+// everything outside of the regions. print `memory_map()` for the full map
+
+use crate::*;
+
+// each oam entry is 8 bytes: x, y, tile index, attributes, sprite size, tile bank, blend, unused
+pub const OAM_START: u16 = 0x0000;
+pub const OAM_ENTRY_BYTES: usize = 8;
+pub const OAM_BYTES: usize = 256 * OAM_ENTRY_BYTES;
+
+pub const OAM_X: usize = 0;
+pub const OAM_Y: usize = 1;
+pub const OAM_TILE_INDEX: usize = 2;
+pub const OAM_ATTRIBUTES: usize = 3;
+// 0 is 8x8, then 8x16, 16x16, 16x32 and 32x32
+pub const OAM_SIZE: usize = 4;
+pub const OAM_TILE_BANK: usize = 5;
+// 0 is opaque, then add, subtract and average
+pub const OAM_BLEND: usize = 6;
+
+// r, g, b for each palette entry
+pub const PALETTE_START: u16 = 0x0800;
+pub const PALETTE_BYTES: usize = 256 * 3;
+
+// one byte each
+pub const CONTROL_START: u16 = 0x0b00;
+pub const CONTROL_BYTES: usize = 8;
+
+pub const REG_BACKGROUND_COLOR: u16 = CONTROL_START;
+// 0 or 1
+pub const REG_OAM_HIDDEN: u16 = CONTROL_START + 1;
+// 0 is unlimited, then drop and flicker
+pub const REG_OBJECT_LIMIT: u16 = CONTROL_START + 2;
+pub const REG_FADE_AMOUNT: u16 = CONTROL_START + 3;
+pub const REG_FADE_R: u16 = CONTROL_START + 4;
+pub const REG_FADE_G: u16 = CONTROL_START + 5;
+pub const REG_FADE_B: u16 = CONTROL_START + 6;
+// low byte of the frame counter, read only
+pub const REG_FRAME_COUNTER: u16 = CONTROL_START + 7;
+
+// 8 bytes per layer: x, y, hidden, tile bank, blend, unused
+pub const BG_REGISTERS_START: u16 = 0x0b10;
+pub const BG_REGISTER_BYTES: usize = 8;
+pub const BG_REGISTERS_BYTES: usize = NUM_OBJECT_PRIORITY_LEVELS * BG_REGISTER_BYTES;
+
+pub const BG_REG_X: usize = 0;
+pub const BG_REG_Y: usize = 1;
+pub const BG_REG_HIDDEN: usize = 2;
+pub const BG_REG_TILE_BANK: usize = 3;
+pub const BG_REG_BLEND: usize = 4;
+
+// 4 bytes per window: left, right, top, bottom
+pub const WINDOW_REGISTERS_START: u16 = 0x0b40;
+pub const WINDOW_REGISTERS_BYTES: usize = NUM_WINDOWS * 4;
+
+// one tile index per map tile, row by row, for each layer. a layer narrower than the max
+// is packed, so its rows are `width` bytes apart
+pub const TILEMAP_START: u16 = 0x1000;
+pub const TILEMAP_BYTES: usize = MAX_BG_SIZE * MAX_BG_SIZE;
+
+// one TileAttributes byte per map tile, laid out like the tilemaps
+pub const ATTRIBUTE_MAP_START: u16 = 0x2000;
+pub const ATTRIBUTE_MAP_BYTES: usize = MAX_BG_SIZE * MAX_BG_SIZE;
+
+// each tile is its planes one after the other, each plane a byte per row
+pub const TILESET_START: u16 = 0x4000;
+pub const TILE_BYTES: usize = NUM_PLANES * BYTES_PER_TILE_PLANE;
+pub const TILESET_BYTES: usize = NUM_TILES * TILE_BYTES;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryRegion {
+    pub name: &'static str,
+    pub start: u16,
+    pub len: usize,
+}
+
+impl MemoryRegion {
+    // last address, inclusive
+    pub fn end(&self) -> u16 {
+        self.start + (self.len - 1) as u16
+    }
+
+    pub fn contains(&self, address: u16) -> bool {
+        address >= self.start && address <= self.end()
+    }
+}
+
+pub const MEMORY_MAP: [MemoryRegion; 9] = [
+    MemoryRegion {
+        name: "oam",
+        start: OAM_START,
+        len: OAM_BYTES,
+    },
+    MemoryRegion {
+        name: "palette",
+        start: PALETTE_START,
+        len: PALETTE_BYTES,
+    },
+    MemoryRegion {
+        name: "control registers",
+        start: CONTROL_START,
+        len: CONTROL_BYTES,
+    },
+    MemoryRegion {
+        name: "bg registers",
+        start: BG_REGISTERS_START,
+        len: BG_REGISTERS_BYTES,
+    },
+    MemoryRegion {
+        name: "window registers",
+        start: WINDOW_REGISTERS_START,
+        len: WINDOW_REGISTERS_BYTES,
+    },
+    MemoryRegion {
+        name: "tilemaps",
+        start: TILEMAP_START,
+        len: NUM_OBJECT_PRIORITY_LEVELS * TILEMAP_BYTES,
+    },
+    MemoryRegion {
+        name: "attribute maps",
+        start: ATTRIBUTE_MAP_START,
+        len: NUM_OBJECT_PRIORITY_LEVELS * ATTRIBUTE_MAP_BYTES,
+    },
+    MemoryRegion {
+        name: "tileset",
+        start: TILESET_START,
+        len: TILESET_BYTES,
+    },
+    // the very end of the address space, so it's clear how much is left
+    MemoryRegion {
+        name: "unmapped",
+        start: TILESET_START + TILESET_BYTES as u16,
+        len: 0x10000 - (TILESET_START as usize + TILESET_BYTES),
+    },
+];
+
+// the memory map as a table, one region per line
+pub fn memory_map() -> String {
+    MEMORY_MAP
+        .iter()
+        .map(|region| {
+            format!(
+                "{:04x}-{:04x}  {:>5}  {}\n",
+                region.start,
+                region.end(),
+                region.len,
+                region.name
+            )
+        })
+        .collect()
+}
+
+// where an address ends up
+enum Location {
+    Oam(usize, usize),
+    Palette(usize, usize),
+    Control(u16),
+    Bg(usize, usize),
+    Window(usize, usize),
+    Tilemap(usize, usize),
+    AttributeMap(usize, usize),
+    Tileset(usize, usize, usize),
+}
+
+fn decode(address: u16) -> Option<Location> {
+    let [oam, palette, control, bg, window, tilemap, attribute_map, tileset, _] = MEMORY_MAP;
+    let offset = |region: MemoryRegion| (address - region.start) as usize;
+
+    let location = if oam.contains(address) {
+        let offset = offset(oam);
+        Location::Oam(offset / OAM_ENTRY_BYTES, offset % OAM_ENTRY_BYTES)
+    } else if palette.contains(address) {
+        let offset = offset(palette);
+        Location::Palette(offset / 3, offset % 3)
+    } else if control.contains(address) {
+        Location::Control(address)
+    } else if bg.contains(address) {
+        let offset = offset(bg);
+        Location::Bg(offset / BG_REGISTER_BYTES, offset % BG_REGISTER_BYTES)
+    } else if window.contains(address) {
+        let offset = offset(window);
+        Location::Window(offset / 4, offset % 4)
+    } else if tilemap.contains(address) {
+        let offset = offset(tilemap);
+        Location::Tilemap(offset / TILEMAP_BYTES, offset % TILEMAP_BYTES)
+    } else if attribute_map.contains(address) {
+        let offset = offset(attribute_map);
+        Location::AttributeMap(offset / ATTRIBUTE_MAP_BYTES, offset % ATTRIBUTE_MAP_BYTES)
+    } else if tileset.contains(address) {
+        let offset = offset(tileset);
+        let (tile, offset) = (offset / TILE_BYTES, offset % TILE_BYTES);
+        Location::Tileset(
+            tile,
+            offset / BYTES_PER_TILE_PLANE,
+            offset % BYTES_PER_TILE_PLANE,
+        )
+    } else {
+        return None;
+    };
+
+    Some(location)
+}
+
+impl Vfc {
+    pub fn peek(&self, address: u16) -> u8 {
+        self.peek_location(address).unwrap_or(0)
+    }
+
+    fn peek_location(&self, address: u16) -> Option<u8> {
+        let value = match decode(address)? {
+            Location::Oam(index, field) => {
+                let entry = self.oam.0.get(index)?;
+                match field {
+                    OAM_X => entry.x,
+                    OAM_Y => entry.y,
+                    OAM_TILE_INDEX => entry.tile_index.0,
+                    OAM_ATTRIBUTES => entry.attributes.to_byte(),
+                    OAM_SIZE => entry.size.to_byte(),
+                    OAM_TILE_BANK => entry.tile_bank.0,
+                    OAM_BLEND => BlendMode::to_byte(entry.blend),
+                    _ => 0,
+                }
+            }
+            Location::Palette(index, channel) => {
+                if index >= self.palette.len() {
+                    return None;
+                }
+                let rgb = self.palette[PaletteIndex(index as u8)];
+                [rgb.r(), rgb.g(), rgb.b()][channel]
+            }
+            Location::Control(register) => match register {
+                REG_BACKGROUND_COLOR => self.background_color.0,
+                REG_OAM_HIDDEN => self.oam_hidden as u8,
+                REG_OBJECT_LIMIT => self.object_limit.to_byte(),
+                REG_FADE_AMOUNT => self.fade.amount,
+                REG_FADE_R => self.fade.color.r(),
+                REG_FADE_G => self.fade.color.g(),
+                REG_FADE_B => self.fade.color.b(),
+                REG_FRAME_COUNTER => self.frame_counter as u8,
+                _ => 0,
+            },
+            Location::Bg(layer, register) => {
+                let layer = self.bg_layers.get(layer)?;
+                match register {
+                    BG_REG_X => layer.x,
+                    BG_REG_Y => layer.y,
+                    BG_REG_HIDDEN => layer.hidden as u8,
+                    BG_REG_TILE_BANK => layer.tile_bank.0,
+                    BG_REG_BLEND => BlendMode::to_byte(layer.blend),
+                    _ => 0,
+                }
+            }
+            Location::Window(window, side) => self.windows[window].to_bytes()[side],
+            Location::Tilemap(layer, index) => self.bg_layers.get(layer)?.tiles.get(index)?.0,
+            Location::AttributeMap(layer, index) => {
+                self.bg_layers.get(layer)?.attributes.get(index)?.to_byte()
+            }
+            Location::Tileset(tile, plane, row) => self.tileset.pixel_data()[plane][tile][row],
+        };
+
+        Some(value)
+    }
+
+    pub fn poke(&mut self, address: u16, value: u8) {
+        self.poke_location(address, value);
+    }
+
+    fn poke_location(&mut self, address: u16, value: u8) -> Option<()> {
+        match decode(address)? {
+            Location::Oam(index, field) => {
+                let entry = self.oam.0.get_mut(index)?;
+                match field {
+                    OAM_X => entry.x = value,
+                    OAM_Y => entry.y = value,
+                    OAM_TILE_INDEX => entry.tile_index = TileIndex(value),
+                    OAM_ATTRIBUTES => entry.attributes = TileAttributes::from_byte(value),
+                    OAM_SIZE => entry.size = SpriteSize::from_byte(value)?,
+                    OAM_TILE_BANK => entry.tile_bank = TileBank(value),
+                    OAM_BLEND => entry.blend = BlendMode::from_byte(value),
+                    _ => (),
+                }
+            }
+            Location::Palette(index, channel) => {
+                if index >= self.palette.len() {
+                    return None;
+                }
+                let rgb = &mut self.palette[PaletteIndex(index as u8)];
+                let mut channels = [rgb.r(), rgb.g(), rgb.b()];
+                channels[channel] = value;
+                *rgb = Rgb::new(channels[0], channels[1], channels[2]);
+            }
+            Location::Control(register) => match register {
+                REG_BACKGROUND_COLOR => self.background_color = PaletteIndex(value),
+                REG_OAM_HIDDEN => self.oam_hidden = value != 0,
+                REG_OBJECT_LIMIT => self.object_limit = ObjectLimit::from_byte(value)?,
+                REG_FADE_AMOUNT => self.fade.amount = value,
+                REG_FADE_R => {
+                    let color = self.fade.color;
+                    self.fade.color = Rgb::new(value, color.g(), color.b());
+                }
+                REG_FADE_G => {
+                    let color = self.fade.color;
+                    self.fade.color = Rgb::new(color.r(), value, color.b());
+                }
+                REG_FADE_B => {
+                    let color = self.fade.color;
+                    self.fade.color = Rgb::new(color.r(), color.g(), value);
+                }
+                _ => (),
+            },
+            Location::Bg(layer, register) => {
+                let layer = self.bg_layers.get_mut(layer)?;
+                match register {
+                    BG_REG_X => layer.x = value,
+                    BG_REG_Y => layer.y = value,
+                    BG_REG_HIDDEN => layer.hidden = value != 0,
+                    BG_REG_TILE_BANK => layer.tile_bank = TileBank(value),
+                    BG_REG_BLEND => layer.blend = BlendMode::from_byte(value),
+                    _ => (),
+                }
+            }
+            Location::Window(window, side) => {
+                let mut bytes = self.windows[window].to_bytes();
+                bytes[side] = value;
+                self.windows[window] = Window::from_bytes(bytes);
+            }
+            Location::Tilemap(layer, index) => {
+                *self.bg_layers.get_mut(layer)?.tiles.get_mut(index)? = TileIndex(value);
+            }
+            Location::AttributeMap(layer, index) => {
+                *self.bg_layers.get_mut(layer)?.attributes.get_mut(index)? =
+                    TileAttributes::from_byte(value);
+            }
+            Location::Tileset(tile, plane, row) => {
+                self.tileset.pixel_data_mut()[plane][tile][row] = value
+            }
+        }
+
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regions_dont_overlap() {
+        let mut regions = MEMORY_MAP.to_vec();
+        regions.sort_by_key(|region| region.start);
+
+        for pair in regions.windows(2) {
+            assert!(pair[0].end() < pair[1].start, "{:?}", pair);
+        }
+        assert_eq!(regions.last().unwrap().end(), u16::MAX);
+        assert_eq!(memory_map().lines().count(), MEMORY_MAP.len());
+    }
+
+    #[test]
+    fn peek_and_poke() {
+        let mut fc = Vfc::with_config(VfcConfig::new().with_bg_size(16, 32)).unwrap();
+
+        let oam_5 = OAM_START + 5 * OAM_ENTRY_BYTES as u16;
+        fc.poke(oam_5 + OAM_X as u16, 12);
+        fc.poke(oam_5 + OAM_Y as u16, 34);
+        fc.poke(oam_5 + OAM_TILE_INDEX as u16, 7);
+        fc.poke(oam_5 + OAM_SIZE as u16, 2);
+        fc.poke(oam_5 + OAM_BLEND as u16, 3);
+        assert_eq!(fc.oam[OamIndex(5)].x, 12);
+        assert_eq!(fc.oam[OamIndex(5)].y, 34);
+        assert_eq!(fc.oam[OamIndex(5)].tile_index, TileIndex(7));
+        assert_eq!(fc.oam[OamIndex(5)].size, SpriteSize::Size16x16);
+        assert_eq!(fc.oam[OamIndex(5)].blend, Some(BlendMode::Average));
+        // bad sizes are ignored
+        fc.poke(oam_5 + OAM_SIZE as u16, 9);
+        assert_eq!(fc.peek(oam_5 + OAM_SIZE as u16), 2);
+
+        fc.poke(PALETTE_START + 3 * 10 + 1, 0xab);
+        assert_eq!(fc.palette[PaletteIndex(10)], Rgb::new(0, 0xab, 0));
+        // the default palette has 64 entries
+        fc.poke(PALETTE_START + 3 * 64, 0xff);
+        assert_eq!(fc.peek(PALETTE_START + 3 * 64), 0);
+
+        fc.poke(REG_FADE_AMOUNT, 80);
+        fc.poke(REG_FADE_G, 200);
+        assert_eq!(fc.fade, Fade::new(Rgb::new(0, 200, 0), 80));
+
+        let bg_1 = BG_REGISTERS_START + BG_REGISTER_BYTES as u16;
+        fc.poke(bg_1 + BG_REG_Y as u16, 99);
+        fc.poke(bg_1 + BG_REG_HIDDEN as u16, 1);
+        assert_eq!(fc.bg_layers[1].y, 99);
+        assert!(fc.bg_layers[1].hidden);
+        // there are only two layers
+        fc.poke(bg_1 + 2 * BG_REGISTER_BYTES as u16, 1);
+        assert_eq!(fc.peek(bg_1 + 2 * BG_REGISTER_BYTES as u16), 0);
+
+        fc.poke(WINDOW_REGISTERS_START + 4 + 3, 50);
+        assert_eq!(fc.windows[1].bottom, 50);
+
+        // tile (3, 2) of a 16 tile wide map
+        let tile = 2 * 16 + 3;
+        fc.poke(TILEMAP_START + TILEMAP_BYTES as u16 + tile, 42);
+        fc.poke(ATTRIBUTE_MAP_START + tile, 0b01_000_101);
+        assert_eq!(
+            fc.bg_layers[1].tiles[fc.bg_layers[1].get_map_index(3, 2)],
+            TileIndex(42)
+        );
+        assert_eq!(
+            fc.bg_layers[0].attributes[tile as usize].get_palette(),
+            Subpalette::new(5)
+        );
+        assert_eq!(fc.bg_layers[0].attributes[tile as usize].get_priority(), 1);
+
+        // tile 300, plane 2, row 6
+        let row = TILESET_START + (300 * TILE_BYTES + 2 * BYTES_PER_TILE_PLANE + 6) as u16;
+        fc.poke(row, 0b1010_0101);
+        assert_eq!(fc.tileset.pixel_data()[2][300][6], 0b1010_0101);
+        assert_eq!(fc.peek(row), 0b1010_0101);
+
+        fc.frame_counter = 0x1234;
+        fc.poke(REG_FRAME_COUNTER, 0);
+        assert_eq!(fc.peek(REG_FRAME_COUNTER), 0x34);
+
+        assert_eq!(fc.peek(0xffff), 0);
+        fc.poke(0xffff, 1);
+    }
+}
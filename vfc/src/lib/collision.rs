@@ -0,0 +1,219 @@
+// pixel-perfect collision detection, opt in with `Vfc::enable_collisions`
+//
+// while a frame is rendered, every opaque sprite pixel is checked against the opaque pixels
+// of the other sprites and of each bg layer. only what's actually drawn counts: sprites dropped
+// by the object limit, hidden layers and pixels outside of a layer's window never collide.
+// priority doesn't matter, a sprite behind a layer still touches it
+
+use crate::*;
+
+// what one sprite touched in the last frame
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ObjectCollision {
+    // one bit per oam index
+    sprites: [u64; 4],
+    // bit n is set when the sprite touched bg layer n
+    pub bg_layers: u8,
+    // the first scanline where the sprite touched any bg layer
+    pub first_bg_line: Option<u8>,
+}
+
+impl ObjectCollision {
+    pub fn with_sprite(&self, oam_index: OamIndex) -> bool {
+        let i = oam_index.0 as usize;
+        self.sprites[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    pub fn any_sprite(&self) -> bool {
+        self.sprites.iter().any(|&bits| bits != 0)
+    }
+
+    // every sprite this one touched, lowest oam index first
+    pub fn sprites(&self) -> impl Iterator<Item = OamIndex> + '_ {
+        (0..=u8::MAX)
+            .map(OamIndex)
+            .filter(|&oam_index| self.with_sprite(oam_index))
+    }
+
+    pub fn with_bg_layer(&self, layer_index: u8) -> bool {
+        self.bg_layers & (1 << layer_index) != 0
+    }
+
+    pub fn any_bg_layer(&self) -> bool {
+        self.bg_layers != 0
+    }
+
+    fn add_sprite(&mut self, oam_index: OamIndex) {
+        let i = oam_index.0 as usize;
+        self.sprites[i / 64] |= 1 << (i % 64);
+    }
+}
+
+// what every sprite touched in the last frame, indexed by oam index
+#[derive(Debug, Clone)]
+pub struct Collisions {
+    pub objects: Vec<ObjectCollision>,
+    // the opaque sprites at each pixel of the current scanline, kept between lines so they
+    // don't allocate every time
+    line: Vec<Vec<OamIndex>>,
+}
+
+impl Collisions {
+    pub fn new(num_oam_entries: usize) -> Collisions {
+        Collisions {
+            objects: vec![ObjectCollision::default(); num_oam_entries],
+            line: vec![],
+        }
+    }
+
+    pub fn get(&self, oam_index: OamIndex) -> ObjectCollision {
+        self.objects
+            .get(oam_index.0 as usize)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    // the first scanline where sprite 0 touched the background, like the nes sprite 0 hit flag.
+    // good for timing a split screen without counting lines
+    pub fn sprite_0_hit(&self) -> Option<u8> {
+        self.get(OamIndex(0)).first_bg_line
+    }
+
+    pub fn clear(&mut self) {
+        self.objects.fill(ObjectCollision::default());
+    }
+}
+
+impl Vfc {
+    pub fn enable_collisions(&mut self) {
+        if self.collisions.is_none() {
+            self.collisions = Some(Collisions::new(self.oam.0.len()));
+        }
+    }
+
+    pub fn disable_collisions(&mut self) {
+        self.collisions = None;
+    }
+
+    // check the sprites drawn on a scanline, with the registers the line is drawn with
+    pub(crate) fn detect_collisions(&mut self, object_list: &[OamIndex], scanline: u8) {
+        let Some(mut collisions) = self.collisions.take() else {
+            return;
+        };
+
+        if !self.oam_hidden {
+            let mut line = std::mem::take(&mut collisions.line);
+            line.resize(self.config.screen_width, vec![]);
+            line.iter_mut().for_each(Vec::clear);
+
+            for &oam_index in object_list {
+                let object = &self.oam[oam_index];
+
+                for local_x in 0..object.width() {
+                    let x = object.x.wrapping_add(local_x);
+
+                    if x as usize >= line.len()
+                        || !self.window_allows(&self.oam_window, x, scanline)
+                        || self.get_tile_pixel_global(oam_index, x, scanline) == RawPixel(0)
+                    {
+                        continue;
+                    }
+
+                    line[x as usize].push(oam_index);
+                }
+            }
+
+            for (x, objects) in line.iter().enumerate() {
+                if objects.is_empty() {
+                    continue;
+                }
+
+                let bg_layers = self
+                    .bg_layers
+                    .iter()
+                    .enumerate()
+                    .take(8)
+                    .filter(|(_, layer)| {
+                        self.bg_layer_pixel(layer, x as u8, scanline)
+                            .is_some_and(|(pixel, _)| pixel != RawPixel(0))
+                    })
+                    .fold(0, |bits, (layer_index, _)| bits | 1 << layer_index);
+
+                for &oam_index in objects {
+                    let Some(collision) = collisions.objects.get_mut(oam_index.0 as usize) else {
+                        continue;
+                    };
+
+                    for &other in objects {
+                        if other != oam_index {
+                            collision.add_sprite(other);
+                        }
+                    }
+
+                    collision.bg_layers |= bg_layers;
+                    if bg_layers != 0 && collision.first_bg_line.is_none() {
+                        collision.first_bg_line = Some(scanline);
+                    }
+                }
+            }
+
+            collisions.line = line;
+        }
+
+        self.collisions = Some(collisions);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collisions() {
+        let mut fc = Vfc::new();
+        fc.enable_collisions();
+
+        // tile 1 is the left half of a tile, tile 2 is solid
+        fc.tileset.write_tile(
+            TileIndex(1),
+            [[0b0000_1111; BYTES_PER_TILE_PLANE]; NUM_PLANES],
+        );
+        fc.tileset
+            .write_tile(TileIndex(2), [[0xff; BYTES_PER_TILE_PLANE]; NUM_PLANES]);
+
+        // a bg 1 tile at (8, 40)
+        fc.bg_layers[1].tiles[5 * BG_WIDTH + 1] = TileIndex(2);
+
+        // 0 overlaps the bg tile from line 40, 1 and 2 overlap each other's opaque pixels,
+        // 3 only overlaps the transparent half of 1
+        let attributes = TileAttributes::default();
+        fc.oam[OamIndex(0)] = OamEntry::new(4, 36, TileIndex(2), attributes);
+        fc.oam[OamIndex(1)] = OamEntry::new(100, 100, TileIndex(1), attributes);
+        fc.oam[OamIndex(2)] = OamEntry::new(102, 104, TileIndex(2), attributes);
+        fc.oam[OamIndex(3)] = OamEntry::new(104, 96, TileIndex(2), attributes);
+
+        fc.render_frame();
+
+        let collisions = fc.collisions.as_ref().unwrap();
+        assert_eq!(collisions.sprite_0_hit(), Some(40));
+        assert!(collisions.get(OamIndex(0)).with_bg_layer(1));
+        assert!(!collisions.get(OamIndex(0)).with_bg_layer(0));
+        assert!(!collisions.get(OamIndex(0)).any_sprite());
+
+        assert_eq!(
+            collisions.get(OamIndex(1)).sprites().collect::<Vec<_>>(),
+            [OamIndex(2)]
+        );
+        assert_eq!(
+            collisions.get(OamIndex(2)).sprites().collect::<Vec<_>>(),
+            [OamIndex(1)]
+        );
+        assert!(!collisions.get(OamIndex(3)).any_sprite());
+        assert!(!collisions.get(OamIndex(1)).any_bg_layer());
+
+        // hidden layers don't collide, and the last frame is forgotten
+        fc.bg_layers[1].hidden = true;
+        fc.render_frame();
+        assert_eq!(fc.collisions.as_ref().unwrap().sprite_0_hit(), None);
+    }
+}
@@ -0,0 +1,205 @@
+// runtime geometry: screen size, number of layers, map size, oam size and palette size
+//
+// the constants in constants.rs are the default config
+
+use crate::*;
+
+// screen coordinates are u8. the lines below the screen are where hidden sprites go,
+// so there has to be room for the tallest sprite there
+pub const MAX_SCREEN_WIDTH: usize = 256;
+pub const MAX_SCREEN_HEIGHT: usize = 256 - 32;
+// map scroll registers are u8, so a map can be at most 256 pixels across
+pub const MAX_BG_SIZE: usize = 256 / TILE_SIZE;
+// palette indices are u8
+pub const MAX_PALETTE_ENTRIES: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VfcConfig {
+    pub screen_width: usize,
+    pub screen_height: usize,
+    pub num_bg_layers: usize,
+    // bg map size in tiles. a power of two, so scrolling wraps around the map cleanly
+    pub bg_width: usize,
+    pub bg_height: usize,
+    pub num_oam_entries: usize,
+    pub num_palette_entries: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    ScreenSize { width: usize, height: usize },
+    BgLayers(usize),
+    BgSize { width: usize, height: usize },
+    OamEntries(usize),
+    PaletteEntries(usize),
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ConfigError::ScreenSize { width, height } => write!(
+                f,
+                "a {width}x{height} screen is not supported, \
+                 it has to be at least 1x1 and at most {MAX_SCREEN_WIDTH}x{MAX_SCREEN_HEIGHT}"
+            ),
+            ConfigError::BgLayers(n) => write!(
+                f,
+                "{n} bg layers is too many, there are only {NUM_OBJECT_PRIORITY_LEVELS} priority levels"
+            ),
+            ConfigError::BgSize { width, height } => write!(
+                f,
+                "a {width}x{height} bg map is not supported, \
+                 both sides have to be powers of two up to {MAX_BG_SIZE}"
+            ),
+            ConfigError::OamEntries(n) => {
+                write!(f, "{n} oam entries is too many, oam indices only go up to 255")
+            }
+            ConfigError::PaletteEntries(n) => write!(
+                f,
+                "a palette of {n} entries is not supported, \
+                 it has to have 1 to {MAX_PALETTE_ENTRIES} entries"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Default for VfcConfig {
+    fn default() -> Self {
+        Self {
+            screen_width: SCREEN_WIDTH,
+            screen_height: SCREEN_HEIGHT,
+            num_bg_layers: NUM_BG_LAYERS,
+            bg_width: BG_WIDTH,
+            bg_height: BG_HEIGHT,
+            num_oam_entries: NUM_OAM_ENTRIES,
+            num_palette_entries: NUM_PALETTE_ENTRIES,
+        }
+    }
+}
+
+impl VfcConfig {
+    pub fn new() -> VfcConfig {
+        VfcConfig::default()
+    }
+
+    // build
+
+    pub fn with_screen_size(mut self, width: usize, height: usize) -> VfcConfig {
+        self.screen_width = width;
+        self.screen_height = height;
+        self
+    }
+
+    pub fn with_bg_layers(mut self, num_bg_layers: usize) -> VfcConfig {
+        self.num_bg_layers = num_bg_layers;
+        self
+    }
+
+    pub fn with_bg_size(mut self, width: usize, height: usize) -> VfcConfig {
+        self.bg_width = width;
+        self.bg_height = height;
+        self
+    }
+
+    pub fn with_oam_entries(mut self, num_oam_entries: usize) -> VfcConfig {
+        self.num_oam_entries = num_oam_entries;
+        self
+    }
+
+    pub fn with_palette_entries(mut self, num_palette_entries: usize) -> VfcConfig {
+        self.num_palette_entries = num_palette_entries;
+        self
+    }
+
+    // read
+
+    pub fn num_screen_pixels(&self) -> usize {
+        self.screen_width * self.screen_height
+    }
+
+    pub fn num_bg_tiles(&self) -> usize {
+        self.bg_width * self.bg_height
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let bg_size_ok = |size: usize| size.is_power_of_two() && size <= MAX_BG_SIZE;
+
+        if !(1..=MAX_SCREEN_WIDTH).contains(&self.screen_width)
+            || !(1..=MAX_SCREEN_HEIGHT).contains(&self.screen_height)
+        {
+            Err(ConfigError::ScreenSize {
+                width: self.screen_width,
+                height: self.screen_height,
+            })
+        } else if self.num_bg_layers > NUM_OBJECT_PRIORITY_LEVELS {
+            Err(ConfigError::BgLayers(self.num_bg_layers))
+        } else if !bg_size_ok(self.bg_width) || !bg_size_ok(self.bg_height) {
+            Err(ConfigError::BgSize {
+                width: self.bg_width,
+                height: self.bg_height,
+            })
+        } else if self.num_oam_entries > 256 {
+            Err(ConfigError::OamEntries(self.num_oam_entries))
+        } else if !(1..=MAX_PALETTE_ENTRIES).contains(&self.num_palette_entries) {
+            Err(ConfigError::PaletteEntries(self.num_palette_entries))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate() {
+        assert_eq!(VfcConfig::default().validate(), Ok(()));
+        assert_eq!(
+            VfcConfig::new()
+                .with_screen_size(160, 144)
+                .with_bg_layers(4)
+                .with_bg_size(16, 32)
+                .with_oam_entries(40)
+                .with_palette_entries(16)
+                .validate(),
+            Ok(())
+        );
+
+        assert_eq!(
+            VfcConfig::new().with_screen_size(256, 240).validate(),
+            Err(ConfigError::ScreenSize {
+                width: 256,
+                height: 240
+            })
+        );
+        assert_eq!(
+            VfcConfig::new().with_bg_layers(5).validate(),
+            Err(ConfigError::BgLayers(5))
+        );
+        assert_eq!(
+            VfcConfig::new().with_bg_size(24, 32).validate(),
+            Err(ConfigError::BgSize {
+                width: 24,
+                height: 32
+            })
+        );
+        assert_eq!(
+            VfcConfig::new().with_bg_size(64, 32).validate(),
+            Err(ConfigError::BgSize {
+                width: 64,
+                height: 32
+            })
+        );
+        assert_eq!(
+            VfcConfig::new().with_oam_entries(257).validate(),
+            Err(ConfigError::OamEntries(257))
+        );
+        assert_eq!(
+            VfcConfig::new().with_palette_entries(0).validate(),
+            Err(ConfigError::PaletteEntries(0))
+        );
+    }
+}
@@ -1,13 +1,19 @@
+// the screen, layer, map, oam and palette sizes here are the default VfcConfig.
+// a Vfc built with another config has its own sizes, see Vfc::config
+
 // screen constants
 pub const SCREEN_WIDTH: usize = 192;
-pub const SCREEN_HEIGHT: usize = 160; // 144?
+pub const SCREEN_HEIGHT: usize = 160;
 pub const NUM_SCREEN_PIXELS: usize = SCREEN_WIDTH * SCREEN_HEIGHT;
 
 // tile constants
 pub const NUM_PLANES: usize = 3;
 pub const BYTES_PER_TILE_PLANE: usize = TILE_WIDTH * TILE_HEIGHT / 8;
 pub const TILE_INDEX_BITS: usize = 8;
-pub const NUM_TILES: usize = 2_usize.pow(TILE_INDEX_BITS as u32);
+pub const TILES_PER_BANK: usize = 2_usize.pow(TILE_INDEX_BITS as u32);
+pub const TILE_BANK_BITS: usize = 2;
+pub const NUM_TILE_BANKS: usize = 2_usize.pow(TILE_BANK_BITS as u32);
+pub const NUM_TILES: usize = TILES_PER_BANK * NUM_TILE_BANKS;
 
 // oam constants
 pub const NUM_OAM_ENTRIES: usize = 256;
@@ -0,0 +1,354 @@
+// debug views of the tileset, bg maps and oam, drawn as images
+//
+// none of these go through the renderer: they read the tiles and tables directly, so they show
+// what's in memory even when it isn't on screen. fades and blending are left out
+
+use crate::*;
+
+// outlines, text and the other things drawn on top of the views
+pub const DEBUG_HIGHLIGHT: Rgb = Rgb(0xffff_00ff);
+pub const DEBUG_TEXT: Rgb = Rgb(0xffff_ffff);
+pub const DEBUG_BACKDROP: Rgb = Rgb(0xff20_2020);
+// the checkerboard behind transparent sprite pixels
+pub const DEBUG_CHECKER: [Rgb; 2] = [Rgb(0xff40_4040), Rgb(0xff58_5858)];
+
+// tiles per row of a tileset sheet. 32 tiles across puts the four banks one under the other
+pub const DEBUG_SHEET_COLUMNS: usize = 32;
+
+// oam grid cells are the biggest sprite with two lines of text under it
+pub const OAM_GRID_COLUMNS: usize = 16;
+const OAM_CELL_WIDTH: usize = 36;
+const OAM_CELL_HEIGHT: usize = 48;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DebugImage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Rgb>,
+}
+
+impl DebugImage {
+    pub fn new(width: usize, height: usize, color: Rgb) -> DebugImage {
+        DebugImage {
+            width,
+            height,
+            pixels: vec![color; width * height],
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Rgb {
+        self.pixels[y * self.width + x]
+    }
+
+    // pixels off the edge are ignored
+    pub fn set(&mut self, x: usize, y: usize, color: Rgb) {
+        if x < self.width && y < self.height {
+            self.pixels[y * self.width + x] = color;
+        }
+    }
+
+    #[cfg(feature = "image")]
+    pub fn to_image(&self) -> image::RgbImage {
+        image::RgbImage::from_fn(self.width as u32, self.height as u32, |x, y| {
+            let rgb = self.get(x as usize, y as usize);
+            image::Rgb([rgb.r(), rgb.g(), rgb.b()])
+        })
+    }
+
+    #[cfg(feature = "image")]
+    pub fn save_png<P: AsRef<std::path::Path>>(&self, path: P) -> image::ImageResult<()> {
+        self.to_image().save(path)
+    }
+
+    // hex digits in a 3x5 font, one pixel apart
+    fn draw_hex(&mut self, x: usize, y: usize, value: u8, color: Rgb) {
+        for (i, digit) in [value >> 4, value & 0xf].into_iter().enumerate() {
+            for (row, bits) in HEX_FONT[digit as usize].iter().enumerate() {
+                for column in 0..3 {
+                    if bits & (0b100 >> column) != 0 {
+                        self.set(x + i * 4 + column, y + row, color);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// 0 to f, each row's leftmost pixel in bit 2
+const HEX_FONT: [[u8; 5]; 16] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b001, 0b001, 0b001],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+    [0b111, 0b101, 0b111, 0b101, 0b101],
+    [0b110, 0b101, 0b110, 0b101, 0b110],
+    [0b111, 0b100, 0b100, 0b100, 0b111],
+    [0b110, 0b101, 0b101, 0b101, 0b110],
+    [0b111, 0b100, 0b111, 0b100, 0b111],
+    [0b111, 0b100, 0b111, 0b100, 0b100],
+];
+
+// every tile in the tileset, all banks, colored with one subpalette. tile n of bank b is at
+// column n % 32, row b * 8 + n / 32. hli's tileset_sheet is one bank, laid out for importing
+pub fn full_tileset_sheet(fc: &Vfc, subpalette: Subpalette) -> DebugImage {
+    let rows = NUM_TILES / DEBUG_SHEET_COLUMNS;
+    let mut image = DebugImage::new(
+        DEBUG_SHEET_COLUMNS * TILE_WIDTH,
+        rows * TILE_HEIGHT,
+        DEBUG_BACKDROP,
+    );
+
+    for tile in 0..NUM_TILES {
+        let bank = TileBank((tile / TILES_PER_BANK) as u8);
+        let tile_index = TileIndex((tile % TILES_PER_BANK) as u8);
+        let (left, top) = (
+            tile % DEBUG_SHEET_COLUMNS * TILE_WIDTH,
+            tile / DEBUG_SHEET_COLUMNS * TILE_HEIGHT,
+        );
+
+        for pixel_y in 0..TILE_HEIGHT {
+            for pixel_x in 0..TILE_WIDTH {
+                let pixel = fc.get_tile_pixel(bank, tile_index, pixel_x as u8, pixel_y as u8);
+                let rgb = fc.palette[subpalette.colorize_pixel(pixel)];
+
+                image.set(left + pixel_x, top + pixel_y, rgb);
+            }
+        }
+    }
+
+    image
+}
+
+// a full tileset sheet for every subpalette, in a grid four sheets wide
+pub fn full_tileset_sheets(fc: &Vfc) -> DebugImage {
+    const SHEETS_PER_ROW: usize = 4;
+
+    // one for each value of the 3 palette bits in TileAttributes
+    let num_subpalettes: usize = 8;
+    let sheets = (0..num_subpalettes)
+        .map(|subpalette| full_tileset_sheet(fc, Subpalette::new(subpalette as u8)))
+        .collect::<Vec<_>>();
+
+    let (sheet_width, sheet_height) = (sheets[0].width, sheets[0].height);
+    let mut image = DebugImage::new(
+        sheet_width * SHEETS_PER_ROW,
+        sheet_height * num_subpalettes.div_ceil(SHEETS_PER_ROW),
+        DEBUG_BACKDROP,
+    );
+
+    for (i, sheet) in sheets.iter().enumerate() {
+        let (left, top) = (
+            i % SHEETS_PER_ROW * sheet_width,
+            i / SHEETS_PER_ROW * sheet_height,
+        );
+
+        for y in 0..sheet_height {
+            for x in 0..sheet_width {
+                image.set(left + x, top + y, sheet.get(x, y));
+            }
+        }
+    }
+
+    image
+}
+
+// a whole bg map, with the part that's on screen outlined. transparent pixels are the background
+// color. an affine layer's outline is the screen's edge mapped onto the map, so it turns and
+// scales with the layer
+pub fn bg_layer_map(fc: &Vfc, layer_index: usize) -> DebugImage {
+    let layer = &fc.bg_layers[layer_index];
+    let mut image = DebugImage::new(
+        layer.pixel_width(),
+        layer.pixel_height(),
+        fc.palette[fc.background_color],
+    );
+
+    for tile_y in 0..layer.height() {
+        for tile_x in 0..layer.width() {
+            let map_index = layer.get_map_index(tile_x, tile_y);
+            let attributes = &layer.attributes[map_index];
+
+            for pixel_y in 0..TILE_HEIGHT {
+                for pixel_x in 0..TILE_WIDTH {
+                    let pixel = fc.get_tile_pixel_rotated(
+                        layer.tile_bank,
+                        layer.tiles[map_index],
+                        attributes,
+                        SpriteSize::Size8x8,
+                        pixel_x as u8,
+                        pixel_y as u8,
+                    );
+
+                    if pixel != RawPixel(0) {
+                        image.set(
+                            tile_x * TILE_WIDTH + pixel_x,
+                            tile_y * TILE_HEIGHT + pixel_y,
+                            fc.palette[attributes.get_palette().colorize_pixel(pixel)],
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    let config = fc.config();
+    let (right, bottom) = (config.screen_width - 1, config.screen_height - 1);
+    let edge = (0..=right)
+        .flat_map(|x| [(x, 0), (x, bottom)])
+        .chain((0..=bottom).flat_map(|y| [(0, y), (right, y)]));
+
+    for (screen_x, screen_y) in edge {
+        let map_pixel = layer.map_pixel_at(
+            (layer.x, layer.y),
+            layer.affine.as_ref(),
+            screen_x as u8,
+            screen_y as u8,
+        );
+
+        if let Some((map_x, map_y)) = map_pixel {
+            image.set(map_x as usize, map_y as usize, DEBUG_HIGHLIGHT);
+        }
+    }
+
+    image
+}
+
+// every oam entry in a grid, sixteen to a row. each cell has the sprite drawn over a
+// checkerboard, then its x and y, and its tile index and attributes byte, in hex
+pub fn oam_grid(fc: &Vfc) -> DebugImage {
+    let rows = fc.oam.0.len().div_ceil(OAM_GRID_COLUMNS);
+    let mut image = DebugImage::new(
+        OAM_GRID_COLUMNS * OAM_CELL_WIDTH,
+        rows * OAM_CELL_HEIGHT,
+        DEBUG_BACKDROP,
+    );
+
+    for (i, entry) in fc.oam.0.iter().enumerate() {
+        let (left, top) = (
+            i % OAM_GRID_COLUMNS * OAM_CELL_WIDTH + 2,
+            i / OAM_GRID_COLUMNS * OAM_CELL_HEIGHT + 2,
+        );
+        let subpalette = entry.attributes.get_palette();
+
+        for local_y in 0..entry.height() {
+            for local_x in 0..entry.width() {
+                let pixel = fc.get_tile_pixel_rotated(
+                    entry.tile_bank,
+                    entry.tile_index,
+                    &entry.attributes,
+                    entry.size,
+                    local_x,
+                    local_y,
+                );
+
+                let rgb = if pixel == RawPixel(0) {
+                    DEBUG_CHECKER[(local_x as usize / 4 + local_y as usize / 4) % 2]
+                } else {
+                    fc.palette[subpalette.colorize_pixel(pixel)]
+                };
+
+                image.set(left + local_x as usize, top + local_y as usize, rgb);
+            }
+        }
+
+        let text_top = top + 32 + 1;
+        image.draw_hex(left, text_top, entry.x, DEBUG_TEXT);
+        image.draw_hex(left + 12, text_top, entry.y, DEBUG_TEXT);
+        image.draw_hex(left, text_top + 6, entry.tile_index.0, DEBUG_TEXT);
+        image.draw_hex(left + 12, text_top + 6, entry.attributes.0, DEBUG_TEXT);
+    }
+
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scene() -> Vfc {
+        let mut fc = Vfc::new();
+        fc.palette = Vfc::test_palette();
+        fc.background_color = PaletteIndex(1);
+
+        // tile 1 of bank 0 has just its top left pixel set, to 7
+        let mut tile = [[0; BYTES_PER_TILE_PLANE]; NUM_PLANES];
+        for plane in tile.iter_mut() {
+            plane[0] = 1;
+        }
+        fc.tileset.write_tile(TileIndex(1), tile);
+        fc.tileset
+            .write_tile_in_bank(TileBank(2), TileIndex(33), tile);
+
+        fc
+    }
+
+    #[test]
+    fn tileset_sheet_layout() {
+        let fc = scene();
+
+        let sheet = full_tileset_sheet(&fc, Subpalette::new(2));
+        assert_eq!((sheet.width, sheet.height), (256, 256));
+        assert_eq!(sheet.get(8, 0), fc.palette[PaletteIndex(2 * 8 + 7)]);
+        assert_eq!(sheet.get(9, 0), fc.palette[PaletteIndex(2 * 8)]);
+        // bank 2 starts on row 16
+        assert_eq!(sheet.get(8, 17 * 8), fc.palette[PaletteIndex(2 * 8 + 7)]);
+
+        let sheets = full_tileset_sheets(&fc);
+        assert_eq!((sheets.width, sheets.height), (1024, 512));
+        assert_eq!(
+            sheets.get(256 * 3 + 8, 256),
+            fc.palette[PaletteIndex(7 * 8 + 7)]
+        );
+    }
+
+    #[test]
+    fn bg_layer_viewport() {
+        let mut fc = scene();
+        let map_index = fc.bg_layers[0].get_map_index(2, 0);
+        fc.bg_layers[0].tiles[map_index] = TileIndex(1);
+        fc.bg_layers[0].x = 100;
+        fc.bg_layers[0].y = 10;
+
+        let map = bg_layer_map(&fc, 0);
+        assert_eq!((map.width, map.height), (256, 256));
+        assert_eq!(map.get(16, 0), fc.palette[PaletteIndex(7)]);
+        assert_eq!(map.get(17, 0), fc.palette[PaletteIndex(1)]);
+
+        // the screen's top left is at map pixel (256 - 100, 256 - 10), and it wraps
+        assert_eq!(map.get(156, 246), DEBUG_HIGHLIGHT);
+        assert_eq!(map.get(156 + 191 - 256, 246), DEBUG_HIGHLIGHT);
+        assert_eq!(map.get(156, 246 + 159 - 256), DEBUG_HIGHLIGHT);
+        assert_eq!(map.get(157, 247), fc.palette[PaletteIndex(1)]);
+    }
+
+    #[test]
+    fn oam_grid_cells() {
+        let mut fc = scene();
+        fc.oam[OamIndex(17)] = OamEntry::new(
+            0xab,
+            0x40,
+            TileIndex(1),
+            TileAttributes::default().with_palette(Subpalette::new(3)),
+        );
+
+        let grid = oam_grid(&fc);
+        assert_eq!((grid.width, grid.height), (16 * 36, 16 * 48));
+
+        // entry 17 is in column 1 of row 1
+        let (left, top) = (36 + 2, 48 + 2);
+        assert_eq!(grid.get(left, top), fc.palette[PaletteIndex(3 * 8 + 7)]);
+        assert_eq!(grid.get(left + 1, top), DEBUG_CHECKER[0]);
+        assert_eq!(grid.get(left + 4, top), DEBUG_CHECKER[1]);
+
+        // "a" of the x position, whose top row is solid
+        assert_eq!(grid.get(left, top + 33), DEBUG_TEXT);
+        assert_eq!(grid.get(left + 2, top + 33), DEBUG_TEXT);
+        assert_eq!(grid.get(left + 1, top + 34), DEBUG_BACKDROP);
+    }
+}
@@ -0,0 +1,157 @@
+// brightness and fade-to-color registers, applied as pixels come out of the palette
+
+use crate::*;
+
+// mixes every pixel toward `color`. an amount of 0 leaves pixels alone, 255 paints them solid
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fade {
+    pub color: Rgb,
+    pub amount: u8,
+}
+
+impl Default for Fade {
+    fn default() -> Self {
+        Self::none()
+    }
+}
+
+impl Fade {
+    pub fn new(color: Rgb, amount: u8) -> Fade {
+        Fade { color, amount }
+    }
+
+    pub fn none() -> Fade {
+        Fade::to_black(0)
+    }
+
+    pub fn to_black(amount: u8) -> Fade {
+        Fade::new(Rgb::new(0, 0, 0), amount)
+    }
+
+    pub fn to_white(amount: u8) -> Fade {
+        Fade::new(Rgb::new(255, 255, 255), amount)
+    }
+
+    // master brightness from -255 (black) through 0 (unchanged) to 255 (white)
+    pub fn brightness(brightness: i16) -> Fade {
+        let amount = brightness.unsigned_abs().min(255) as u8;
+
+        if brightness < 0 {
+            Fade::to_black(amount)
+        } else {
+            Fade::to_white(amount)
+        }
+    }
+
+    pub fn is_none(&self) -> bool {
+        self.amount == 0
+    }
+
+    pub fn apply(&self, rgb: Rgb) -> Rgb {
+        if self.is_none() {
+            return rgb;
+        }
+
+        Rgb::new(
+            lerp(rgb.r(), self.color.r(), self.amount as u32, 255),
+            lerp(rgb.g(), self.color.g(), self.amount as u32, 255),
+            lerp(rgb.b(), self.color.b(), self.amount as u32, 255),
+        )
+    }
+}
+
+fn lerp(from: u8, to: u8, step: u32, steps: u32) -> u8 {
+    let (from, to) = (from as i32, to as i32);
+
+    (from + (to - from) * step as i32 / steps as i32) as u8
+}
+
+// steps from one fade to another over a number of frames
+//
+// call `advance` once a frame and put the result in `Vfc::fade` (or a layer's fade)
+#[derive(Debug, Clone)]
+pub struct FadeAnimation {
+    pub start: Fade,
+    pub end: Fade,
+    pub frames: u32,
+    pub elapsed: u32,
+}
+
+impl FadeAnimation {
+    pub fn new(start: Fade, end: Fade, frames: u32) -> FadeAnimation {
+        FadeAnimation {
+            start,
+            end,
+            frames,
+            elapsed: 0,
+        }
+    }
+
+    pub fn fade_out(color: Rgb, frames: u32) -> FadeAnimation {
+        FadeAnimation::new(Fade::new(color, 0), Fade::new(color, 255), frames)
+    }
+
+    pub fn fade_in(color: Rgb, frames: u32) -> FadeAnimation {
+        FadeAnimation::new(Fade::new(color, 255), Fade::new(color, 0), frames)
+    }
+
+    pub fn current(&self) -> Fade {
+        if self.is_finished() {
+            return self.end;
+        }
+
+        let lerp = |from, to| lerp(from, to, self.elapsed, self.frames);
+
+        Fade {
+            color: Rgb::new(
+                lerp(self.start.color.r(), self.end.color.r()),
+                lerp(self.start.color.g(), self.end.color.g()),
+                lerp(self.start.color.b(), self.end.color.b()),
+            ),
+            amount: lerp(self.start.amount, self.end.amount),
+        }
+    }
+
+    // move one frame forward and return the fade for the new frame
+    pub fn advance(&mut self) -> Fade {
+        if !self.is_finished() {
+            self.elapsed += 1;
+        }
+
+        self.current()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.elapsed >= self.frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fade_apply() {
+        let rgb = Rgb::new(0x80, 0x40, 0xff);
+
+        assert_eq!(Fade::none().apply(rgb), rgb);
+        assert_eq!(Fade::to_black(255).apply(rgb), Rgb::new(0, 0, 0));
+        assert_eq!(Fade::to_white(255).apply(rgb), Rgb::new(255, 255, 255));
+        assert_eq!(Fade::brightness(-51).apply(rgb), Rgb::new(0x67, 0x34, 0xcc));
+        assert_eq!(Fade::brightness(51).apply(rgb), Rgb::new(0x99, 0x66, 0xff));
+    }
+
+    #[test]
+    fn fade_animation() {
+        let mut fade = FadeAnimation::fade_out(Rgb::new(0, 0, 0), 4);
+
+        assert_eq!(fade.current().amount, 0);
+        assert_eq!(fade.advance().amount, 63);
+        assert_eq!(fade.advance().amount, 127);
+        assert_eq!(fade.advance().amount, 191);
+        assert!(!fade.is_finished());
+        assert_eq!(fade.advance().amount, 255);
+        assert!(fade.is_finished());
+        assert_eq!(fade.advance().amount, 255);
+    }
+}
@@ -1,8 +1,8 @@
 use crate::constants::*;
-use crate::{TileAttributes, TileIndex};
+use crate::{BlendMode, TileAttributes, TileBank, TileIndex, MAX_SCREEN_HEIGHT};
 
 #[repr(transparent)]
-pub struct OamTable(pub [OamEntry; NUM_OAM_ENTRIES]);
+pub struct OamTable(pub Vec<OamEntry>);
 
 #[derive(Debug, Clone)]
 pub struct OamEntry {
@@ -10,12 +10,138 @@ pub struct OamEntry {
     pub y: u8,
     pub tile_index: TileIndex,
     pub attributes: TileAttributes,
+    pub size: SpriteSize,
+    pub tile_bank: TileBank,
+    // makes the sprite translucent, mixing it with whatever is below
+    pub blend: Option<BlendMode>,
+}
+
+// sprites bigger than one tile use sequential tile indices, left to right then top to bottom
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum SpriteSize {
+    #[default]
+    Size8x8,
+    Size8x16,
+    Size16x16,
+    Size16x32,
+    Size32x32,
+}
+
+impl SpriteSize {
+    pub fn width(&self) -> usize {
+        use SpriteSize::*;
+
+        match self {
+            Size8x8 | Size8x16 => 8,
+            Size16x16 | Size16x32 => 16,
+            Size32x32 => 32,
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        use SpriteSize::*;
+
+        match self {
+            Size8x8 => 8,
+            Size8x16 | Size16x16 => 16,
+            Size16x32 | Size32x32 => 32,
+        }
+    }
+
+    pub fn tiles_wide(&self) -> usize {
+        self.width() / TILE_WIDTH
+    }
+
+    pub fn tiles_high(&self) -> usize {
+        self.height() / TILE_HEIGHT
+    }
+
+    // the byte the bus and save states use
+    pub fn to_byte(&self) -> u8 {
+        *self as u8
+    }
+
+    pub fn from_byte(value: u8) -> Option<SpriteSize> {
+        use SpriteSize::*;
+
+        match value {
+            0 => Some(Size8x8),
+            1 => Some(Size8x16),
+            2 => Some(Size16x16),
+            3 => Some(Size16x32),
+            4 => Some(Size32x32),
+            _ => None,
+        }
+    }
 }
 
 #[repr(transparent)]
 #[derive(Debug, Default, Clone, Copy, PartialEq)]
 pub struct OamIndex(pub u8);
 
+// what to do with objects past OBJECTS_PER_LINE on a scanline
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum ObjectLimit {
+    // draw every object, no matter how many share a line
+    #[default]
+    Unlimited,
+    // drop objects past the limit, the lowest OAM indices win
+    Drop,
+    // drop objects past the limit, rotating which ones each frame so they take turns flickering
+    Flicker,
+}
+
+impl ObjectLimit {
+    // the byte the bus and save states use
+    pub fn to_byte(&self) -> u8 {
+        *self as u8
+    }
+
+    pub fn from_byte(value: u8) -> Option<ObjectLimit> {
+        match value {
+            0 => Some(ObjectLimit::Unlimited),
+            1 => Some(ObjectLimit::Drop),
+            2 => Some(ObjectLimit::Flicker),
+            _ => None,
+        }
+    }
+}
+
+// which scanlines had more than OBJECTS_PER_LINE objects in the last frame
+#[derive(Debug, Clone)]
+pub struct ObjectOverflow {
+    pub lines: Vec<bool>,
+}
+
+impl Default for ObjectOverflow {
+    fn default() -> Self {
+        Self::new(SCREEN_HEIGHT)
+    }
+}
+
+impl ObjectOverflow {
+    pub fn new(screen_height: usize) -> ObjectOverflow {
+        ObjectOverflow {
+            lines: vec![false; screen_height],
+        }
+    }
+
+    pub fn any(&self) -> bool {
+        self.lines.iter().any(|&overflow| overflow)
+    }
+
+    pub fn first_line(&self) -> Option<u8> {
+        self.lines
+            .iter()
+            .position(|&overflow| overflow)
+            .map(|line| line as u8)
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.fill(false);
+    }
+}
+
 impl std::ops::Index<OamIndex> for OamTable {
     type Output = OamEntry;
 
@@ -32,7 +158,13 @@ impl std::ops::IndexMut<OamIndex> for OamTable {
 
 impl Default for OamTable {
     fn default() -> Self {
-        Self([(); NUM_OAM_ENTRIES].map(|_| OamEntry::default()))
+        Self::new(NUM_OAM_ENTRIES)
+    }
+}
+
+impl OamTable {
+    pub fn new(num_entries: usize) -> OamTable {
+        OamTable(vec![OamEntry::default(); num_entries])
     }
 }
 
@@ -40,9 +172,12 @@ impl Default for OamEntry {
     fn default() -> Self {
         Self {
             x: 0,
-            y: SCREEN_HEIGHT as u8,
+            y: MAX_SCREEN_HEIGHT as u8,
             tile_index: TileIndex(0),
             attributes: TileAttributes::oam_default(),
+            size: SpriteSize::default(),
+            tile_bank: TileBank::default(),
+            blend: None,
         }
     }
 }
@@ -54,26 +189,48 @@ impl OamEntry {
             y,
             tile_index,
             attributes,
+            size: SpriteSize::default(),
+            tile_bank: TileBank::default(),
+            blend: None,
         }
     }
 
-    pub fn bounding_box_contains_pixel(&self, x: u8, y: u8) -> bool {
-        let left = self.x;
-        let right = self.x.wrapping_add(TILE_WIDTH as u8);
-        let top = self.y;
-        let bottom = self.y.wrapping_add(TILE_HEIGHT as u8);
+    pub fn with_size(mut self, size: SpriteSize) -> OamEntry {
+        self.size = size;
+        self
+    }
+
+    pub fn with_tile_bank(mut self, tile_bank: TileBank) -> OamEntry {
+        self.tile_bank = tile_bank;
+        self
+    }
 
-        let horizontal = if left <= right {
-            x >= left && x < right
+    pub fn with_blend(mut self, blend: BlendMode) -> OamEntry {
+        self.blend = Some(blend);
+        self
+    }
+
+    // on-screen width, which is the height of the sprite when it is flipped diagonally
+    pub fn width(&self) -> u8 {
+        if self.attributes.get_flip_diagonal() {
+            self.size.height() as u8
         } else {
-            x >= left || x < right
-        };
+            self.size.width() as u8
+        }
+    }
 
-        let vertical = if top <= bottom {
-            y >= top && y < bottom
+    pub fn height(&self) -> u8 {
+        if self.attributes.get_flip_diagonal() {
+            self.size.width() as u8
         } else {
-            y >= top || y < bottom
-        };
+            self.size.height() as u8
+        }
+    }
+
+    pub fn bounding_box_contains_pixel(&self, x: u8, y: u8) -> bool {
+        // wrapping, so sprites hanging off the left or top edge work
+        let horizontal = x.wrapping_sub(self.x) < self.width();
+        let vertical = y.wrapping_sub(self.y) < self.height();
 
         horizontal && vertical
     }
@@ -107,7 +264,8 @@ impl OamEntry {
     }
     */
 
+    // move the sprite below the screen, where it stays hidden on any screen size
     pub fn hide(&mut self) {
-        self.y = SCREEN_HEIGHT as u8;
+        self.y = MAX_SCREEN_HEIGHT as u8;
     }
 }
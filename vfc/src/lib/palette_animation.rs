@@ -0,0 +1,243 @@
+// palette animation: ranges of the palette that cycle or step through keyframes on their own
+//
+// every animation in `Vfc::palette_animations` is advanced once at the end of each rendered
+// frame, after raster writes to the palette have been undone. the palette itself is changed,
+// so whatever is drawn with those entries animates without touching the tilemaps
+
+use crate::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PaletteAnimationKind {
+    // rotate `len` entries from `start` by one entry each step. forward moves every color up
+    // one entry, with the last one wrapping around to `start`
+    Rotate {
+        start: PaletteIndex,
+        len: u8,
+        reverse: bool,
+    },
+    // show one keyframe at a time in the entries from `start`, looping back to the first.
+    // the current keyframe is written every frame, so the entries can't drift from it
+    Keyframes {
+        start: PaletteIndex,
+        keyframes: Vec<Vec<Rgb>>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaletteAnimation {
+    pub kind: PaletteAnimationKind,
+    pub frames_per_step: u32,
+    // frames since the last step
+    pub elapsed: u32,
+    // steps taken, modulo the length of the cycle. for keyframes, the keyframe being shown
+    pub step: u32,
+    pub paused: bool,
+}
+
+impl PaletteAnimation {
+    fn new(kind: PaletteAnimationKind, frames_per_step: u32) -> PaletteAnimation {
+        PaletteAnimation {
+            kind,
+            frames_per_step,
+            elapsed: 0,
+            step: 0,
+            paused: false,
+        }
+    }
+
+    pub fn rotate(start: PaletteIndex, len: u8, frames_per_step: u32) -> PaletteAnimation {
+        PaletteAnimation::new(
+            PaletteAnimationKind::Rotate {
+                start,
+                len,
+                reverse: false,
+            },
+            frames_per_step,
+        )
+    }
+
+    // rotate the colors of a subpalette, leaving entry 0 alone since it's transparent
+    pub fn rotate_subpalette(subpalette: Subpalette, frames_per_step: u32) -> PaletteAnimation {
+        let start = subpalette.get() * SUBPALETTE_SIZE as u8 + 1;
+
+        PaletteAnimation::rotate(
+            PaletteIndex(start),
+            SUBPALETTE_SIZE as u8 - 1,
+            frames_per_step,
+        )
+    }
+
+    pub fn keyframes(
+        start: PaletteIndex,
+        keyframes: Vec<Vec<Rgb>>,
+        frames_per_step: u32,
+    ) -> PaletteAnimation {
+        PaletteAnimation::new(
+            PaletteAnimationKind::Keyframes { start, keyframes },
+            frames_per_step,
+        )
+    }
+
+    pub fn reversed(mut self) -> PaletteAnimation {
+        if let PaletteAnimationKind::Rotate { reverse, .. } = &mut self.kind {
+            *reverse = !*reverse;
+        }
+        self
+    }
+
+    // steps before the animation is back where it started
+    pub fn cycle_len(&self) -> u32 {
+        match &self.kind {
+            PaletteAnimationKind::Rotate { len, .. } => *len as u32,
+            PaletteAnimationKind::Keyframes { keyframes, .. } => keyframes.len() as u32,
+        }
+    }
+
+    // move one frame forward, changing the palette if it's time for a step
+    pub fn advance(&mut self, palette: &mut Palette) {
+        if self.paused || self.cycle_len() == 0 {
+            return;
+        }
+
+        self.elapsed += 1;
+
+        let stepped = self.elapsed >= self.frames_per_step.max(1);
+        if stepped {
+            self.elapsed = 0;
+            self.step = (self.step + 1) % self.cycle_len();
+        }
+
+        match &self.kind {
+            PaletteAnimationKind::Rotate {
+                start,
+                len,
+                reverse,
+            } => {
+                if !stepped {
+                    return;
+                }
+
+                let entries = (0..*len)
+                    .map(|i| PaletteIndex(start.0.wrapping_add(i)))
+                    .collect::<Vec<_>>();
+                let mut colors = entries.iter().map(|&i| palette[i]).collect::<Vec<_>>();
+
+                if *reverse {
+                    colors.rotate_left(1);
+                } else {
+                    colors.rotate_right(1);
+                }
+
+                for (&i, rgb) in entries.iter().zip(colors) {
+                    palette[i] = rgb;
+                }
+            }
+            PaletteAnimationKind::Keyframes { start, keyframes } => {
+                for (i, &rgb) in keyframes[self.step as usize].iter().enumerate() {
+                    palette[PaletteIndex(start.0.wrapping_add(i as u8))] = rgb;
+                }
+            }
+        }
+    }
+}
+
+impl Vfc {
+    pub(crate) fn advance_palette_animations(&mut self) {
+        for animation in self.palette_animations.iter_mut() {
+            animation.advance(&mut self.palette);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gray(v: u8) -> Rgb {
+        Rgb::new(v, v, v)
+    }
+
+    #[test]
+    fn rotate() {
+        let mut palette = Palette::new((0..8).map(gray).collect());
+        let mut animation = PaletteAnimation::rotate(PaletteIndex(2), 4, 2);
+
+        animation.advance(&mut palette);
+        assert_eq!(palette[PaletteIndex(2)], gray(2));
+
+        animation.advance(&mut palette);
+        let entries = (0..8)
+            .map(|i| palette[PaletteIndex(i)].r())
+            .collect::<Vec<_>>();
+        assert_eq!(entries, [0, 1, 5, 2, 3, 4, 6, 7]);
+        assert_eq!(animation.step, 1);
+
+        let mut animation = animation.reversed();
+        animation.advance(&mut palette);
+        animation.advance(&mut palette);
+        let entries = (0..8)
+            .map(|i| palette[PaletteIndex(i)].r())
+            .collect::<Vec<_>>();
+        assert_eq!(entries, [0, 1, 2, 3, 4, 5, 6, 7]);
+
+        // subpalette 1 is entries 8 to 15, and 8 is transparent
+        let animation = PaletteAnimation::rotate_subpalette(Subpalette::new(1), 1);
+        assert_eq!(
+            animation.kind,
+            PaletteAnimationKind::Rotate {
+                start: PaletteIndex(9),
+                len: 7,
+                reverse: false
+            }
+        );
+    }
+
+    #[test]
+    fn keyframes_in_vfc() {
+        let mut fc = Vfc::new();
+        fc.palette_animations.push(PaletteAnimation::keyframes(
+            PaletteIndex(4),
+            vec![
+                vec![gray(10), gray(11)],
+                vec![gray(20), gray(21)],
+                vec![gray(30)],
+            ],
+            3,
+        ));
+        // raster writes are undone before the animation runs
+        fc.raster_table.push(
+            10,
+            RegisterWrite::PaletteEntry(PaletteIndex(4), Rgb::new(255, 0, 0)),
+        );
+
+        let mut shown = vec![];
+        for _ in 0..9 {
+            fc.render_frame();
+            shown.push((
+                fc.palette[PaletteIndex(4)].r(),
+                fc.palette[PaletteIndex(5)].r(),
+            ));
+        }
+
+        assert_eq!(
+            shown,
+            [
+                (10, 11),
+                (10, 11),
+                (20, 21),
+                (20, 21),
+                (20, 21),
+                (30, 21),
+                (30, 21),
+                (30, 21),
+                (10, 11)
+            ]
+        );
+
+        fc.palette_animations[0].paused = true;
+        for _ in 0..3 {
+            fc.render_frame();
+        }
+        assert_eq!(fc.palette[PaletteIndex(4)], gray(10));
+    }
+}
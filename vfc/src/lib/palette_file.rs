@@ -0,0 +1,417 @@
+// palettes to and from the files paint programs use: gimp .gpl, plain .hex (one rrggbb per
+// line, like lospec's) and jasc .pal. palettes of up to MAX_PALETTE_ENTRIES colours, shorter
+// ones repeat like any other short palette
+
+use std::path::Path;
+
+use crate::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PaletteFormat {
+    Gpl,
+    Hex,
+    Jasc,
+}
+
+impl PaletteFormat {
+    // from the file extension
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<PaletteFormat> {
+        let extension = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+
+        match extension.as_str() {
+            "gpl" => Some(PaletteFormat::Gpl),
+            "hex" => Some(PaletteFormat::Hex),
+            "pal" => Some(PaletteFormat::Jasc),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PaletteError {
+    Io(std::io::Error),
+    UnknownFormat,
+    // a file without a single colour, which can't be a palette
+    NoColors,
+    // a line that isn't a colour or header of the format, counting from 1
+    Parse { line: usize, text: String },
+    // more colours than the palette or subpalette has room for
+    TooManyColors { colors: usize, max: usize },
+    NoSuchSubpalette(Subpalette),
+}
+
+impl std::fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PaletteError::Io(e) => write!(f, "{e}"),
+            PaletteError::UnknownFormat => {
+                write!(f, "palette files have to be .gpl, .hex or .pal")
+            }
+            PaletteError::NoColors => write!(f, "the palette has no colours"),
+            PaletteError::Parse { line, text } => write!(f, "line {line} is not valid: {text}"),
+            PaletteError::TooManyColors { colors, max } => {
+                write!(f, "{colors} colours, but there's only room for {max}")
+            }
+            PaletteError::NoSuchSubpalette(subpalette) => write!(
+                f,
+                "subpalette {} is past the end of the palette",
+                subpalette.get()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PaletteError {}
+
+impl From<std::io::Error> for PaletteError {
+    fn from(e: std::io::Error) -> PaletteError {
+        PaletteError::Io(e)
+    }
+}
+
+impl Palette {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Palette, PaletteError> {
+        let format = PaletteFormat::from_path(&path).ok_or(PaletteError::UnknownFormat)?;
+
+        Palette::parse(&std::fs::read_to_string(path)?, format)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), PaletteError> {
+        let format = PaletteFormat::from_path(&path).ok_or(PaletteError::UnknownFormat)?;
+
+        std::fs::write(path, self.to_format_string(format))?;
+
+        Ok(())
+    }
+
+    pub fn parse(text: &str, format: PaletteFormat) -> Result<Palette, PaletteError> {
+        let colors = match format {
+            PaletteFormat::Gpl => parse_gpl(text)?,
+            PaletteFormat::Hex => parse_hex(text)?,
+            PaletteFormat::Jasc => parse_jasc(text)?,
+        };
+
+        if colors.is_empty() {
+            return Err(PaletteError::NoColors);
+        }
+
+        if colors.len() > MAX_PALETTE_ENTRIES {
+            return Err(PaletteError::TooManyColors {
+                colors: colors.len(),
+                max: MAX_PALETTE_ENTRIES,
+            });
+        }
+
+        Ok(Palette(colors))
+    }
+
+    pub fn to_format_string(&self, format: PaletteFormat) -> String {
+        let mut text = String::new();
+
+        match format {
+            PaletteFormat::Gpl => {
+                // a subpalette to a row in gimp's palette editor
+                text.push_str(&format!("GIMP Palette\nColumns: {SUBPALETTE_SIZE}\n#\n"));
+                for (index, rgb) in self.0.iter().enumerate() {
+                    text.push_str(&format!(
+                        "{:3} {:3} {:3}\t{index:02x}\n",
+                        rgb.r(),
+                        rgb.g(),
+                        rgb.b()
+                    ));
+                }
+            }
+            PaletteFormat::Hex => {
+                for rgb in &self.0 {
+                    text.push_str(&format!("{:02x}{:02x}{:02x}\n", rgb.r(), rgb.g(), rgb.b()));
+                }
+            }
+            PaletteFormat::Jasc => {
+                text.push_str(&format!("JASC-PAL\n0100\n{}\n", self.0.len()));
+                for rgb in &self.0 {
+                    text.push_str(&format!("{} {} {}\n", rgb.r(), rgb.g(), rgb.b()));
+                }
+            }
+        }
+
+        text
+    }
+}
+
+fn parse_error(line: usize, text: &str) -> PaletteError {
+    PaletteError::Parse {
+        line: line + 1,
+        text: text.to_string(),
+    }
+}
+
+// three whitespace separated numbers, anything after them is ignored (gimp puts names there)
+fn parse_rgb_triple(line: usize, text: &str) -> Result<Rgb, PaletteError> {
+    let mut channels = text.split_whitespace().map(|channel| channel.parse::<u8>());
+
+    match (channels.next(), channels.next(), channels.next()) {
+        (Some(Ok(r)), Some(Ok(g)), Some(Ok(b))) => Ok(Rgb::new(r, g, b)),
+        _ => Err(parse_error(line, text)),
+    }
+}
+
+fn parse_gpl(text: &str) -> Result<Vec<Rgb>, PaletteError> {
+    let mut lines = text.lines().enumerate();
+
+    match lines.next() {
+        Some((_, header)) if header.trim() == "GIMP Palette" => (),
+        Some((line, header)) => return Err(parse_error(line, header)),
+        None => return Err(parse_error(0, "")),
+    }
+
+    lines
+        .filter(|(_, text)| {
+            let text = text.trim();
+            !(text.is_empty()
+                || text.starts_with('#')
+                || text.starts_with("Name:")
+                || text.starts_with("Columns:"))
+        })
+        .map(|(line, text)| parse_rgb_triple(line, text))
+        .collect()
+}
+
+fn parse_hex(text: &str) -> Result<Vec<Rgb>, PaletteError> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, text)| !text.trim().is_empty())
+        .map(|(line, text)| {
+            let hex = text.trim().trim_start_matches('#');
+
+            match u32::from_str_radix(hex, 16) {
+                Ok(rgb) if hex.len() == 6 => {
+                    let [_, r, g, b] = rgb.to_be_bytes();
+                    Ok(Rgb::new(r, g, b))
+                }
+                _ => Err(parse_error(line, text)),
+            }
+        })
+        .collect()
+}
+
+fn parse_jasc(text: &str) -> Result<Vec<Rgb>, PaletteError> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(line, text)| (line, text.trim()));
+
+    for expected in ["JASC-PAL", "0100"] {
+        match lines.next() {
+            Some((_, header)) if header == expected => (),
+            Some((line, header)) => return Err(parse_error(line, header)),
+            None => return Err(parse_error(0, "")),
+        }
+    }
+
+    let count = match lines.next() {
+        Some((line, count)) => count.parse().map_err(|_| parse_error(line, count))?,
+        None => return Err(parse_error(2, "")),
+    };
+
+    let colors = lines
+        .filter(|(_, text)| !text.is_empty())
+        .map(|(line, text)| parse_rgb_triple(line, text))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if colors.len() != count {
+        return Err(parse_error(
+            2,
+            &format!("{count} colours, but the file has {}", colors.len()),
+        ));
+    }
+
+    Ok(colors)
+}
+
+// a full palette put together a subpalette at a time. entries that are never set are black
+#[derive(Clone)]
+pub struct PaletteBuilder {
+    palette: Palette,
+}
+
+impl Default for PaletteBuilder {
+    fn default() -> Self {
+        PaletteBuilder::new()
+    }
+}
+
+impl PaletteBuilder {
+    // a palette of the default size, NUM_PALETTE_ENTRIES
+    pub fn new() -> PaletteBuilder {
+        PaletteBuilder::with_size(NUM_PALETTE_ENTRIES)
+    }
+
+    // for a config with a different palette size, see VfcConfig::num_palette_entries
+    pub fn with_size(num_entries: usize) -> PaletteBuilder {
+        PaletteBuilder {
+            palette: Palette::with_size(num_entries),
+        }
+    }
+
+    // up to SUBPALETTE_SIZE colours, starting at the subpalette's first entry
+    pub fn with_subpalette(
+        mut self,
+        subpalette: Subpalette,
+        colors: &[Rgb],
+    ) -> Result<PaletteBuilder, PaletteError> {
+        let start = *subpalette.get() as usize * SUBPALETTE_SIZE;
+
+        if start >= self.palette.len() {
+            return Err(PaletteError::NoSuchSubpalette(subpalette));
+        }
+
+        let max = SUBPALETTE_SIZE.min(self.palette.len() - start);
+        if colors.len() > max {
+            return Err(PaletteError::TooManyColors {
+                colors: colors.len(),
+                max,
+            });
+        }
+
+        self.palette.0[start..start + colors.len()].copy_from_slice(colors);
+
+        Ok(self)
+    }
+
+    // a subpalette from a palette file
+    pub fn with_subpalette_file<P: AsRef<Path>>(
+        self,
+        subpalette: Subpalette,
+        path: P,
+    ) -> Result<PaletteBuilder, PaletteError> {
+        let file = Palette::load(path)?;
+
+        self.with_subpalette(subpalette, &file.0)
+    }
+
+    // colours from the first entry on, filling as many subpalettes as they need
+    pub fn with_colors(mut self, colors: &[Rgb]) -> Result<PaletteBuilder, PaletteError> {
+        if colors.len() > self.palette.len() {
+            return Err(PaletteError::TooManyColors {
+                colors: colors.len(),
+                max: self.palette.len(),
+            });
+        }
+
+        self.palette.0[..colors.len()].copy_from_slice(colors);
+
+        Ok(self)
+    }
+
+    pub fn build(self) -> Palette {
+        self.palette
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_round_trip() {
+        let palette = Palette::new((0..20).map(|i| Rgb::new(i * 12, 255 - i, i * 7)).collect());
+
+        for format in [PaletteFormat::Gpl, PaletteFormat::Hex, PaletteFormat::Jasc] {
+            let text = palette.to_format_string(format);
+            let parsed = Palette::parse(&text, format).unwrap();
+            assert_eq!(parsed.0, palette.0);
+        }
+    }
+
+    #[test]
+    fn parse_files() {
+        let gpl =
+            "GIMP Palette\nName: test\nColumns: 4\n#\n  0  16 255\tBlue\n\n255 255 255 White\n";
+        assert_eq!(
+            Palette::parse(gpl, PaletteFormat::Gpl).unwrap().0,
+            [Rgb::new(0, 16, 255), Rgb::new(255, 255, 255)]
+        );
+
+        let hex = "#ff0000\n00ff00\r\n\n";
+        assert_eq!(
+            Palette::parse(hex, PaletteFormat::Hex).unwrap().0,
+            [Rgb::new(255, 0, 0), Rgb::new(0, 255, 0)]
+        );
+
+        match Palette::parse("ff0000\nnope\n", PaletteFormat::Hex) {
+            Err(PaletteError::Parse { line, .. }) => assert_eq!(line, 2),
+            _ => panic!("line 2 isn't a colour"),
+        }
+
+        assert!(Palette::parse("JASC-PAL\n0100\n2\n1 2 3\n", PaletteFormat::Jasc).is_err());
+
+        // empty palettes have nothing to wrap around to
+        for (text, format) in [
+            ("", PaletteFormat::Hex),
+            ("GIMP Palette\n# nothing yet\n", PaletteFormat::Gpl),
+            ("JASC-PAL\n0100\n0\n", PaletteFormat::Jasc),
+        ] {
+            assert!(matches!(
+                Palette::parse(text, format),
+                Err(PaletteError::NoColors)
+            ));
+        }
+
+        // a full 256 colour jasc palette, like most paint programs export
+        let jasc = format!("JASC-PAL\n0100\n256\n{}", "1 2 3\n".repeat(256));
+        assert_eq!(
+            Palette::parse(&jasc, PaletteFormat::Jasc).unwrap().len(),
+            256
+        );
+
+        let too_many = "ffffff\n".repeat(MAX_PALETTE_ENTRIES + 1);
+        assert!(matches!(
+            Palette::parse(&too_many, PaletteFormat::Hex),
+            Err(PaletteError::TooManyColors { colors: 257, .. })
+        ));
+
+        assert_eq!(
+            PaletteFormat::from_path("art/level1.PAL"),
+            Some(PaletteFormat::Jasc)
+        );
+    }
+
+    #[test]
+    fn builder() {
+        let red = Rgb::new(255, 0, 0);
+        let blue = Rgb::new(0, 0, 255);
+
+        let palette = PaletteBuilder::new()
+            .with_colors(&[blue; 3])
+            .unwrap()
+            .with_subpalette(Subpalette::new(7), &[red; SUBPALETTE_SIZE])
+            .unwrap()
+            .build();
+
+        assert_eq!(palette.len(), NUM_PALETTE_ENTRIES);
+        assert_eq!(palette[PaletteIndex(2)], blue);
+        assert_eq!(palette[PaletteIndex(3)], Rgb::default());
+        assert_eq!(palette[PaletteIndex(56)], red);
+        assert_eq!(palette[PaletteIndex(63)], red);
+
+        assert!(matches!(
+            PaletteBuilder::new().with_subpalette(Subpalette::new(1), &[red; 9]),
+            Err(PaletteError::TooManyColors { colors: 9, max: 8 })
+        ));
+        assert!(matches!(
+            PaletteBuilder::new().with_subpalette(Subpalette::new(8), &[red]),
+            Err(PaletteError::NoSuchSubpalette(_))
+        ));
+
+        // the checks follow the palette's size, not the default one
+        let big = PaletteBuilder::with_size(MAX_PALETTE_ENTRIES)
+            .with_colors(&[blue; 200])
+            .unwrap()
+            .build();
+        assert_eq!(big.len(), MAX_PALETTE_ENTRIES);
+        assert!(matches!(
+            PaletteBuilder::with_size(12).with_subpalette(Subpalette::new(1), &[red; 5]),
+            Err(PaletteError::TooManyColors { colors: 5, max: 4 })
+        ));
+    }
+}
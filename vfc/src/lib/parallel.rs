@@ -0,0 +1,119 @@
+// parallel rendering, behind the "parallel" feature
+//
+// raster writes and sprite selection depend on the lines above, so they are worked out first on
+// one thread. after that every line only reads shared state, and bands of lines are drawn on
+// worker threads. the output is exactly what the serial span renderer draws
+
+use rayon::prelude::*;
+
+use crate::*;
+
+// scanlines per job
+const BAND_HEIGHT: usize = 8;
+
+impl Vfc {
+    pub(crate) fn render_frame_parallel(&mut self) {
+        let saved_registers = RasterRegisters::save(self);
+
+        self.object_overflow.clear();
+
+        if let Some(collisions) = &mut self.collisions {
+            collisions.clear();
+        }
+
+        self.tile_cache.refresh(&self.tileset);
+
+        // the registers each line is drawn with (an index into `snapshots`), and its sprites
+        let mut snapshots = vec![saved_registers.clone()];
+        let mut lines = Vec::with_capacity(self.config.screen_height);
+
+        for scanline in 0..self.config.screen_height {
+            let scanline = scanline as u8;
+
+            if !self.raster_table.get(scanline).is_empty() {
+                self.apply_raster_writes(scanline);
+                snapshots.push(RasterRegisters::save(self));
+            }
+
+            let object_list = self.get_objects_on_scanline(scanline);
+
+            let object_list = self.limit_objects_on_scanline(object_list, scanline);
+
+            self.detect_collisions(&object_list, scanline);
+
+            lines.push((snapshots.len() - 1, object_list));
+        }
+
+        self.undo_raster_writes(&saved_registers);
+
+        let width = self.config.screen_width;
+        let band_pixels = width * BAND_HEIGHT;
+
+        let mut framebuffer = std::mem::take(&mut self.framebuffer);
+        let mut indexed_framebuffer = self.indexed_framebuffer.take();
+        let mut pick_buffer = self.pick_buffer.take();
+
+        // each band's piece of every buffer
+        let mut bands = framebuffer
+            .chunks_mut(band_pixels)
+            .map(|framebuffer| LineOutput {
+                framebuffer,
+                indexed_framebuffer: None,
+                pick_buffer: None,
+            })
+            .collect::<Vec<_>>();
+
+        if let Some(indexed_framebuffer) = &mut indexed_framebuffer {
+            for (band, indexed) in bands
+                .iter_mut()
+                .zip(indexed_framebuffer.chunks_mut(band_pixels))
+            {
+                band.indexed_framebuffer = Some(indexed);
+            }
+        }
+
+        if let Some(pick_buffer) = &mut pick_buffer {
+            for (band, picks) in bands.iter_mut().zip(pick_buffer.chunks_mut(band_pixels)) {
+                band.pick_buffer = Some(picks);
+            }
+        }
+
+        let fc = &*self;
+        bands
+            .into_par_iter()
+            .enumerate()
+            .for_each(|(band, mut output)| {
+                let mut line_buffers = LineBuffers::default();
+                let line_range = |i: usize| i * width..(i + 1) * width;
+
+                for i in 0..output.framebuffer.len() / width {
+                    let scanline = band * BAND_HEIGHT + i;
+                    let (snapshot, object_list) = &lines[scanline];
+
+                    fc.draw_line_spans(
+                        &snapshots[*snapshot],
+                        &mut line_buffers,
+                        object_list,
+                        scanline as u8,
+                        LineOutput {
+                            framebuffer: &mut output.framebuffer[line_range(i)],
+                            indexed_framebuffer: output
+                                .indexed_framebuffer
+                                .as_deref_mut()
+                                .map(|indexed| &mut indexed[line_range(i)]),
+                            pick_buffer: output
+                                .pick_buffer
+                                .as_deref_mut()
+                                .map(|picks| &mut picks[line_range(i)]),
+                        },
+                    );
+                }
+            });
+
+        self.framebuffer = framebuffer;
+        self.indexed_framebuffer = indexed_framebuffer;
+        self.pick_buffer = pick_buffer;
+
+        self.finish_frame();
+    }
+}
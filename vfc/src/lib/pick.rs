@@ -0,0 +1,132 @@
+// which sprite or bg tile each pixel of the last frame came from, opt in with
+// `Vfc::enable_pick_buffer`. for clicking on things in editors, and for tests that need to
+// know what ended up on top
+
+use crate::*;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Pick {
+    // nothing was drawn, the pixel is the background color
+    #[default]
+    BgColor,
+    // the map tile of a bg layer, in tiles from the top left of the map
+    BgTile {
+        layer: u8,
+        tile_x: u8,
+        tile_y: u8,
+    },
+    Sprite(OamIndex),
+}
+
+impl Pick {
+    pub fn layer(&self) -> LayerType {
+        match *self {
+            Pick::BgColor => LayerType::BgColor,
+            Pick::BgTile { layer, .. } => LayerType::BgLayer(layer),
+            Pick::Sprite(oam_index) => LayerType::Oam(oam_index),
+        }
+    }
+}
+
+impl Vfc {
+    pub fn enable_pick_buffer(&mut self) {
+        if self.pick_buffer.is_none() {
+            self.pick_buffer = Some(vec![Pick::default(); self.config.num_screen_pixels()]);
+        }
+    }
+
+    pub fn disable_pick_buffer(&mut self) {
+        self.pick_buffer = None;
+    }
+
+    // what's on top at a pixel of the last frame. translucent pixels pick the top layer,
+    // not what it blends with. None if the pick buffer is off or the pixel is off screen
+    pub fn pick(&self, x: u8, y: u8) -> Option<Pick> {
+        if x as usize >= self.config.screen_width || y as usize >= self.config.screen_height {
+            return None;
+        }
+
+        self.pick_buffer
+            .as_ref()
+            .map(|pick_buffer| pick_buffer[self.get_fb_pixel_index(x, y)])
+    }
+
+    // the pick for a pixel drawn by `layer`, with the bg registers the line was drawn with
+    pub(crate) fn get_pick(
+        &self,
+        layer: &LayerType,
+        bg_registers: &[BgRegisters],
+        x: u8,
+        y: u8,
+    ) -> Pick {
+        match *layer {
+            LayerType::BgColor => Pick::BgColor,
+            LayerType::Oam(oam_index) => Pick::Sprite(oam_index),
+            LayerType::BgLayer(layer_index) => {
+                let bg_layer = &self.bg_layers[layer_index as usize];
+                let registers = &bg_registers[layer_index as usize];
+                let scroll = (registers.x, registers.y);
+
+                match bg_layer.map_pixel_at(scroll, registers.affine.as_ref(), x, y) {
+                    Some((map_x, map_y)) => Pick::BgTile {
+                        layer: layer_index,
+                        tile_x: map_x / TILE_WIDTH as u8,
+                        tile_y: map_y / TILE_HEIGHT as u8,
+                    },
+                    None => Pick::BgColor,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pick() {
+        for renderer in [Renderer::PerPixel, Renderer::Span] {
+            let mut fc = Vfc::new();
+            fc.renderer = renderer;
+            assert_eq!(fc.pick(0, 0), None);
+            fc.enable_pick_buffer();
+
+            fc.tileset
+                .write_tile(TileIndex(1), [[0xff; BYTES_PER_TILE_PLANE]; NUM_PLANES]);
+
+            // bg 1 scrolled by (4, 2), with tile (3, 1) filled in
+            fc.bg_layers[1].x = 4;
+            fc.bg_layers[1].y = 2;
+            fc.bg_layers[1].tiles[BG_WIDTH + 3] = TileIndex(1);
+            // scrolled again on the lower half of the screen
+            fc.raster_table.push(80, RegisterWrite::BgX(1, 0));
+
+            fc.oam[OamIndex(7)] = OamEntry::new(50, 50, TileIndex(1), TileAttributes::default())
+                .with_blend(BlendMode::Average);
+
+            fc.render_frame();
+
+            assert_eq!(fc.pick(0, 0), Some(Pick::BgColor));
+            assert_eq!(
+                fc.pick(28, 10),
+                Some(Pick::BgTile {
+                    layer: 1,
+                    tile_x: 3,
+                    tile_y: 1
+                })
+            );
+            assert_eq!(fc.pick(36, 10), Some(Pick::BgColor));
+            assert_eq!(fc.pick(57, 57), Some(Pick::Sprite(OamIndex(7))));
+            assert_eq!(fc.pick(58, 57), Some(Pick::BgColor));
+            assert_eq!(fc.pick(0, SCREEN_HEIGHT as u8), None);
+
+            fc.bg_layers[1].tiles[11 * BG_WIDTH] = TileIndex(1);
+            fc.render_frame();
+            assert_eq!(
+                fc.pick(0, 90).map(|pick| pick.layer()),
+                Some(LayerType::BgLayer(1))
+            );
+        }
+    }
+}
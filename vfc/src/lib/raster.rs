@@ -0,0 +1,199 @@
+// per-scanline register writes, applied during hblank before each line is rendered
+
+use crate::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RegisterWrite {
+    BgX(u8, u8),
+    BgY(u8, u8),
+    BgHidden(u8, bool),
+    BgAffine(u8, Option<Affine>),
+    OamHidden(bool),
+    Window(u8, Window),
+    BackgroundColor(PaletteIndex),
+    PaletteEntry(PaletteIndex, Rgb),
+    Fade(Fade),
+}
+
+// a list of register writes for each scanline
+//
+// the writes only last for the frame being rendered:
+// every register they touch is put back once the frame is done.
+// registers an hblank callback writes are left as it leaves them, unless the table writes them
+// too: then the table wins and they're put back as well
+#[derive(Debug, Clone)]
+pub struct RasterTable {
+    lines: Vec<Vec<RegisterWrite>>,
+}
+
+impl Default for RasterTable {
+    fn default() -> Self {
+        Self {
+            lines: vec![vec![]; SCREEN_HEIGHT],
+        }
+    }
+}
+
+impl RasterTable {
+    pub fn new() -> RasterTable {
+        RasterTable::default()
+    }
+
+    pub fn push(&mut self, scanline: u8, write: RegisterWrite) {
+        // grow for screens taller than the default
+        if scanline as usize >= self.lines.len() {
+            self.lines.resize(scanline as usize + 1, vec![]);
+        }
+
+        self.lines[scanline as usize].push(write);
+    }
+
+    pub fn get(&self, scanline: u8) -> &[RegisterWrite] {
+        match self.lines.get(scanline as usize) {
+            Some(line) => &line[..],
+            None => &[],
+        }
+    }
+
+    pub fn clear_line(&mut self, scanline: u8) {
+        if let Some(line) = self.lines.get_mut(scanline as usize) {
+            line.clear();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for line in self.lines.iter_mut() {
+            line.clear();
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.iter().all(|line| line.is_empty())
+    }
+}
+
+// the registers of a bg layer a RegisterWrite can touch
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct BgRegisters {
+    pub(crate) x: u8,
+    pub(crate) y: u8,
+    pub(crate) hidden: bool,
+    pub(crate) affine: Option<Affine>,
+}
+
+// the registers a RegisterWrite can touch, so they can be put back after a frame.
+// the span renderer also draws each line from a copy of these
+#[derive(Clone)]
+pub(crate) struct RasterRegisters {
+    pub(crate) bg_layers: Vec<BgRegisters>,
+    pub(crate) oam_hidden: bool,
+    pub(crate) windows: [Window; NUM_WINDOWS],
+    pub(crate) background_color: PaletteIndex,
+    pub(crate) palette: Palette,
+    pub(crate) fade: Fade,
+}
+
+impl RasterRegisters {
+    pub(crate) fn save(fc: &Vfc) -> RasterRegisters {
+        RasterRegisters {
+            bg_layers: RasterRegisters::save_bg_layers(fc),
+            oam_hidden: fc.oam_hidden,
+            windows: fc.windows,
+            background_color: fc.background_color,
+            palette: fc.palette.clone(),
+            fade: fc.fade,
+        }
+    }
+
+    pub(crate) fn save_bg_layers(fc: &Vfc) -> Vec<BgRegisters> {
+        fc.bg_layers
+            .iter()
+            .map(|layer| BgRegisters {
+                x: layer.x,
+                y: layer.y,
+                hidden: layer.hidden,
+                affine: layer.affine,
+            })
+            .collect()
+    }
+
+    // the write that puts back what `write` changes, None for a layer or window that isn't there
+    fn undo(&self, write: RegisterWrite) -> Option<RegisterWrite> {
+        use RegisterWrite::*;
+
+        let layer = |layer: u8| self.bg_layers.get(layer as usize);
+
+        match write {
+            BgX(index, _) => layer(index).map(|layer| BgX(index, layer.x)),
+            BgY(index, _) => layer(index).map(|layer| BgY(index, layer.y)),
+            BgHidden(index, _) => layer(index).map(|layer| BgHidden(index, layer.hidden)),
+            BgAffine(index, _) => layer(index).map(|layer| BgAffine(index, layer.affine)),
+            OamHidden(_) => Some(OamHidden(self.oam_hidden)),
+            Window(index, _) => self
+                .windows
+                .get(index as usize)
+                .map(|&window| Window(index, window)),
+            BackgroundColor(_) => Some(BackgroundColor(self.background_color)),
+            PaletteEntry(index, _) => Some(PaletteEntry(index, self.palette[index])),
+            Fade(_) => Some(Fade(self.fade)),
+        }
+    }
+}
+
+impl Vfc {
+    pub fn apply_register_write(&mut self, write: RegisterWrite) {
+        use RegisterWrite::*;
+
+        match write {
+            BgX(layer, x) => {
+                if let Some(layer) = self.bg_layers.get_mut(layer as usize) {
+                    layer.x = x;
+                }
+            }
+            BgY(layer, y) => {
+                if let Some(layer) = self.bg_layers.get_mut(layer as usize) {
+                    layer.y = y;
+                }
+            }
+            BgHidden(layer, hidden) => {
+                if let Some(layer) = self.bg_layers.get_mut(layer as usize) {
+                    layer.hidden = hidden;
+                }
+            }
+            BgAffine(layer, affine) => {
+                if let Some(layer) = self.bg_layers.get_mut(layer as usize) {
+                    layer.affine = affine;
+                }
+            }
+            OamHidden(hidden) => self.oam_hidden = hidden,
+            Window(window_index, window) => {
+                if let Some(w) = self.windows.get_mut(window_index as usize) {
+                    *w = window;
+                }
+            }
+            BackgroundColor(palette_index) => self.background_color = palette_index,
+            PaletteEntry(palette_index, rgb) => self.palette[palette_index] = rgb,
+            Fade(fade) => self.fade = fade,
+        }
+    }
+
+    pub(crate) fn apply_raster_writes(&mut self, scanline: u8) {
+        for i in 0..self.raster_table.get(scanline).len() {
+            let write = self.raster_table.get(scanline)[i];
+            self.apply_register_write(write);
+        }
+    }
+
+    // put back every register the raster table wrote this frame, as it was in `saved`, even if
+    // an hblank callback wrote it since. anything else the callback wrote stays
+    pub(crate) fn undo_raster_writes(&mut self, saved: &RasterRegisters) {
+        for scanline in 0..self.config.screen_height {
+            for i in 0..self.raster_table.get(scanline as u8).len() {
+                let write = self.raster_table.get(scanline as u8)[i];
+                if let Some(undo) = saved.undo(write) {
+                    self.apply_register_write(undo);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,813 @@
+// save states: every register, table and tile of a Vfc packed into a versioned binary blob
+//
+// the layout is little-endian with no padding:
+//
+//   magic "VFCS", version u16, config
+//   oam, oam registers, palette, background color, fade, palette animations,
+//   tileset, bg layers, windows, raster table, frame counter
+//
+// lists are prefixed with their length, options with a 0/1 byte and enums are one byte.
+// the framebuffers, pick buffer, object overflow, collisions and renderer are not saved: all but
+// the renderer come out of the next render_frame, and that's up to whoever is running the Vfc
+
+use crate::*;
+
+pub const SAVE_STATE_MAGIC: [u8; 4] = *b"VFCS";
+pub const SAVE_STATE_VERSION: u16 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SaveStateError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    // the state ended in the middle of something
+    Truncated,
+    // a value that no Vfc could hold, named after what was being read
+    InvalidValue(&'static str),
+    Config(ConfigError),
+}
+
+impl std::fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SaveStateError::BadMagic => write!(f, "not a vfc save state"),
+            SaveStateError::UnsupportedVersion(version) => write!(
+                f,
+                "save state version {version} is not supported, expected {SAVE_STATE_VERSION}"
+            ),
+            SaveStateError::Truncated => write!(f, "save state is truncated"),
+            SaveStateError::InvalidValue(what) => write!(f, "save state has an invalid {what}"),
+            SaveStateError::Config(err) => write!(f, "save state has a bad config: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for SaveStateError {}
+
+impl From<ConfigError> for SaveStateError {
+    fn from(err: ConfigError) -> Self {
+        SaveStateError::Config(err)
+    }
+}
+
+impl Vfc {
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w = StateWriter::default();
+
+        w.bytes(&SAVE_STATE_MAGIC);
+        w.u16(SAVE_STATE_VERSION);
+
+        let config = &self.config;
+        w.u16(config.screen_width as u16);
+        w.u16(config.screen_height as u16);
+        w.u8(config.num_bg_layers as u8);
+        w.u8(config.bg_width as u8);
+        w.u8(config.bg_height as u8);
+        w.u16(config.num_oam_entries as u16);
+        w.u16(config.num_palette_entries as u16);
+
+        w.u16(self.oam.0.len() as u16);
+        for entry in self.oam.0.iter() {
+            w.u8(entry.x);
+            w.u8(entry.y);
+            w.u8(entry.tile_index.0);
+            w.u8(entry.attributes.to_byte());
+            w.u8(entry.size.to_byte());
+            w.u8(entry.tile_bank.0);
+            w.blend(entry.blend);
+        }
+        w.bool(self.oam_hidden);
+        w.window_mask(&self.oam_window);
+        w.option(self.oam_fade.as_ref(), StateWriter::fade);
+        w.u8(self.object_limit.to_byte());
+
+        // always the config's size, so the state loads back. a palette of another size is
+        // saved the way it wraps around to that many entries
+        w.u32(config.num_palette_entries as u32);
+        for index in 0..config.num_palette_entries {
+            w.rgb(self.palette[PaletteIndex(index as u8)]);
+        }
+        w.u8(self.background_color.0);
+        w.fade(&self.fade);
+
+        w.u16(self.palette_animations.len() as u16);
+        for animation in self.palette_animations.iter() {
+            w.palette_animation(animation);
+        }
+
+        for plane in self.tileset.pixel_data().iter() {
+            for tile in plane.iter() {
+                w.bytes(tile);
+            }
+        }
+
+        w.u8(self.bg_layers.len() as u8);
+        for layer in self.bg_layers.iter() {
+            w.u16(layer.width as u16);
+            w.u16(layer.height as u16);
+            w.u8(layer.x);
+            w.u8(layer.y);
+            for tile_index in layer.tiles.iter() {
+                w.u8(tile_index.0);
+            }
+            for attributes in layer.attributes.iter() {
+                w.u8(attributes.to_byte());
+            }
+            w.u8(layer.tile_bank.0);
+            w.option(layer.affine.as_ref(), StateWriter::affine);
+            w.window_mask(&layer.window);
+            w.blend(layer.blend);
+            w.option(layer.fade.as_ref(), StateWriter::fade);
+            w.bool(layer.hidden);
+        }
+
+        for window in self.windows.iter() {
+            w.window(window);
+        }
+
+        let raster_lines = (0..=u8::MAX)
+            .filter(|&scanline| !self.raster_table.get(scanline).is_empty())
+            .collect::<Vec<_>>();
+        w.u16(raster_lines.len() as u16);
+        for scanline in raster_lines {
+            let writes = self.raster_table.get(scanline);
+            w.u8(scanline);
+            w.u16(writes.len() as u16);
+            for write in writes {
+                w.register_write(write);
+            }
+        }
+
+        w.u64(self.frame_counter as u64);
+
+        w.0
+    }
+
+    // a new Vfc, built with the config in the state
+    pub fn from_state(state: &[u8]) -> Result<Vfc, SaveStateError> {
+        let mut r = StateReader::new(state);
+
+        if r.take(SAVE_STATE_MAGIC.len())? != SAVE_STATE_MAGIC {
+            return Err(SaveStateError::BadMagic);
+        }
+
+        let version = r.u16()?;
+        if version != SAVE_STATE_VERSION {
+            return Err(SaveStateError::UnsupportedVersion(version));
+        }
+
+        let config = VfcConfig {
+            screen_width: r.u16()? as usize,
+            screen_height: r.u16()? as usize,
+            num_bg_layers: r.u8()? as usize,
+            bg_width: r.u8()? as usize,
+            bg_height: r.u8()? as usize,
+            num_oam_entries: r.u16()? as usize,
+            num_palette_entries: r.u16()? as usize,
+        };
+        let mut fc = Vfc::with_config(config)?;
+
+        // everything sized by the config has to match it, checked before anything is allocated
+        let num_oam_entries = r.u16()? as usize;
+        if num_oam_entries != config.num_oam_entries {
+            return Err(SaveStateError::InvalidValue("oam size"));
+        }
+        fc.oam = OamTable(
+            (0..num_oam_entries)
+                .map(|_| {
+                    Ok(OamEntry {
+                        x: r.u8()?,
+                        y: r.u8()?,
+                        tile_index: TileIndex(r.u8()?),
+                        attributes: TileAttributes::from_byte(r.u8()?),
+                        size: r.sprite_size()?,
+                        tile_bank: TileBank(r.u8()?),
+                        blend: r.blend()?,
+                    })
+                })
+                .collect::<Result<_, SaveStateError>>()?,
+        );
+        fc.oam_hidden = r.bool()?;
+        fc.oam_window = r.window_mask()?;
+        fc.oam_fade = r.option(StateReader::fade)?;
+        fc.object_limit =
+            ObjectLimit::from_byte(r.u8()?).ok_or(SaveStateError::InvalidValue("object limit"))?;
+
+        let num_palette_entries = r.u32()? as usize;
+        if num_palette_entries != config.num_palette_entries
+            || num_palette_entries > MAX_PALETTE_ENTRIES
+        {
+            return Err(SaveStateError::InvalidValue("palette size"));
+        }
+        fc.palette = Palette(
+            (0..num_palette_entries)
+                .map(|_| r.rgb())
+                .collect::<Result<_, _>>()?,
+        );
+        fc.background_color = PaletteIndex(r.u8()?);
+        fc.fade = r.fade()?;
+
+        let num_palette_animations = r.u16()?;
+        fc.palette_animations = (0..num_palette_animations)
+            .map(|_| r.palette_animation())
+            .collect::<Result<_, _>>()?;
+
+        for plane in fc.tileset.pixel_data_mut().iter_mut() {
+            for tile in plane.iter_mut() {
+                tile.copy_from_slice(r.take(BYTES_PER_TILE_PLANE)?);
+            }
+        }
+
+        let num_bg_layers = r.u8()? as usize;
+        if num_bg_layers != config.num_bg_layers {
+            return Err(SaveStateError::InvalidValue("bg layer count"));
+        }
+        fc.bg_layers = (0..num_bg_layers)
+            .map(|_| {
+                let width = r.u16()? as usize;
+                let height = r.u16()? as usize;
+                if width != config.bg_width || height != config.bg_height {
+                    return Err(SaveStateError::InvalidValue("bg layer size"));
+                }
+
+                let mut layer = BgLayer::new(width, height);
+                layer.x = r.u8()?;
+                layer.y = r.u8()?;
+                for tile_index in layer.tiles.iter_mut() {
+                    *tile_index = TileIndex(r.u8()?);
+                }
+                for attributes in layer.attributes.iter_mut() {
+                    *attributes = TileAttributes::from_byte(r.u8()?);
+                }
+                layer.tile_bank = TileBank(r.u8()?);
+                layer.affine = r.option(StateReader::affine)?;
+                layer.window = r.window_mask()?;
+                layer.blend = r.blend()?;
+                layer.fade = r.option(StateReader::fade)?;
+                layer.hidden = r.bool()?;
+
+                Ok(layer)
+            })
+            .collect::<Result<_, _>>()?;
+
+        for window in fc.windows.iter_mut() {
+            *window = r.window()?;
+        }
+
+        let num_raster_lines = r.u16()?;
+        for _ in 0..num_raster_lines {
+            let scanline = r.u8()?;
+            let num_writes = r.u16()?;
+            for _ in 0..num_writes {
+                let write = r.register_write()?;
+                fc.raster_table.push(scanline, write);
+            }
+        }
+
+        fc.frame_counter = r.u64()? as usize;
+
+        if !r.is_at_end() {
+            return Err(SaveStateError::InvalidValue("length"));
+        }
+
+        Ok(fc)
+    }
+
+    // replace everything with what's in the state. the renderer, and whether the indexed
+    // framebuffer, collisions and pick buffer are kept, stay as they are. on error the Vfc is left untouched
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), SaveStateError> {
+        let mut fc = Vfc::from_state(state)?;
+
+        fc.renderer = self.renderer;
+        if self.indexed_framebuffer.is_some() {
+            fc.enable_indexed_framebuffer();
+        }
+        if self.collisions.is_some() {
+            fc.enable_collisions();
+        }
+        if self.pick_buffer.is_some() {
+            fc.enable_pick_buffer();
+        }
+
+        *self = fc;
+
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct StateWriter(Vec<u8>);
+
+impl StateWriter {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn i32(&mut self, value: i32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    fn option<T>(&mut self, value: Option<&T>, write: fn(&mut Self, &T)) {
+        self.bool(value.is_some());
+        if let Some(value) = value {
+            write(self, value);
+        }
+    }
+
+    fn rgb(&mut self, rgb: Rgb) {
+        self.bytes(&[rgb.r(), rgb.g(), rgb.b()]);
+    }
+
+    fn fade(&mut self, fade: &Fade) {
+        self.rgb(fade.color);
+        self.u8(fade.amount);
+    }
+
+    fn palette_animation(&mut self, animation: &PaletteAnimation) {
+        match &animation.kind {
+            PaletteAnimationKind::Rotate {
+                start,
+                len,
+                reverse,
+            } => self.bytes(&[0, start.0, *len, *reverse as u8]),
+            PaletteAnimationKind::Keyframes { start, keyframes } => {
+                self.bytes(&[1, start.0]);
+                self.u16(keyframes.len() as u16);
+                for keyframe in keyframes.iter() {
+                    self.u16(keyframe.len() as u16);
+                    for rgb in keyframe.iter() {
+                        self.rgb(*rgb);
+                    }
+                }
+            }
+        }
+        self.u32(animation.frames_per_step);
+        self.u32(animation.elapsed);
+        self.u32(animation.step);
+        self.bool(animation.paused);
+    }
+
+    fn blend(&mut self, blend: Option<BlendMode>) {
+        self.u8(BlendMode::to_byte(blend));
+    }
+
+    fn affine(&mut self, affine: &Affine) {
+        for value in [
+            affine.a,
+            affine.b,
+            affine.c,
+            affine.d,
+            affine.screen_center_x,
+            affine.screen_center_y,
+            affine.map_center_x,
+            affine.map_center_y,
+        ] {
+            self.i32(value);
+        }
+        self.u8(affine.edge as u8);
+    }
+
+    fn window(&mut self, window: &Window) {
+        self.bytes(&window.to_bytes());
+    }
+
+    fn window_mask(&mut self, mask: &WindowMask) {
+        self.u8(mask.mode.to_byte());
+        for &enabled in mask.windows.iter() {
+            self.bool(enabled);
+        }
+    }
+
+    fn register_write(&mut self, write: &RegisterWrite) {
+        use RegisterWrite::*;
+
+        match *write {
+            BgX(layer, x) => self.bytes(&[0, layer, x]),
+            BgY(layer, y) => self.bytes(&[1, layer, y]),
+            BgHidden(layer, hidden) => self.bytes(&[2, layer, hidden as u8]),
+            BgAffine(layer, affine) => {
+                self.bytes(&[3, layer]);
+                self.option(affine.as_ref(), Self::affine);
+            }
+            OamHidden(hidden) => self.bytes(&[4, hidden as u8]),
+            Window(window_index, window) => {
+                self.bytes(&[5, window_index]);
+                self.window(&window);
+            }
+            BackgroundColor(palette_index) => self.bytes(&[6, palette_index.0]),
+            PaletteEntry(palette_index, rgb) => {
+                self.bytes(&[7, palette_index.0]);
+                self.rgb(rgb);
+            }
+            Fade(fade) => {
+                self.u8(8);
+                self.fade(&fade);
+            }
+        }
+    }
+}
+
+struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> StateReader<'a> {
+    fn new(data: &'a [u8]) -> StateReader<'a> {
+        StateReader { data, position: 0 }
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.position == self.data.len()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SaveStateError> {
+        let bytes = self
+            .data
+            .get(self.position..self.position + len)
+            .ok_or(SaveStateError::Truncated)?;
+        self.position += len;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], SaveStateError> {
+        Ok(self.take(N)?.try_into().unwrap_or_else(|_| unreachable!()))
+    }
+
+    fn u8(&mut self) -> Result<u8, SaveStateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, SaveStateError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, SaveStateError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, SaveStateError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    fn i32(&mut self) -> Result<i32, SaveStateError> {
+        Ok(i32::from_le_bytes(self.array()?))
+    }
+
+    fn bool(&mut self) -> Result<bool, SaveStateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SaveStateError::InvalidValue("flag")),
+        }
+    }
+
+    fn option<T>(
+        &mut self,
+        read: fn(&mut Self) -> Result<T, SaveStateError>,
+    ) -> Result<Option<T>, SaveStateError> {
+        if self.bool()? {
+            Ok(Some(read(self)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn rgb(&mut self) -> Result<Rgb, SaveStateError> {
+        let [r, g, b] = self.array()?;
+        Ok(Rgb::new(r, g, b))
+    }
+
+    fn fade(&mut self) -> Result<Fade, SaveStateError> {
+        Ok(Fade::new(self.rgb()?, self.u8()?))
+    }
+
+    fn palette_animation(&mut self) -> Result<PaletteAnimation, SaveStateError> {
+        let kind = match self.u8()? {
+            0 => PaletteAnimationKind::Rotate {
+                start: PaletteIndex(self.u8()?),
+                len: self.u8()?,
+                reverse: self.bool()?,
+            },
+            1 => {
+                let start = PaletteIndex(self.u8()?);
+                let num_keyframes = self.u16()?;
+                let keyframes = (0..num_keyframes)
+                    .map(|_| {
+                        let len = self.u16()?;
+                        (0..len).map(|_| self.rgb()).collect()
+                    })
+                    .collect::<Result<_, _>>()?;
+
+                PaletteAnimationKind::Keyframes { start, keyframes }
+            }
+            _ => return Err(SaveStateError::InvalidValue("palette animation")),
+        };
+
+        let animation = PaletteAnimation {
+            kind,
+            frames_per_step: self.u32()?,
+            elapsed: self.u32()?,
+            step: self.u32()?,
+            paused: self.bool()?,
+        };
+        if animation.step >= animation.cycle_len().max(1) {
+            return Err(SaveStateError::InvalidValue("palette animation step"));
+        }
+
+        Ok(animation)
+    }
+
+    fn blend(&mut self) -> Result<Option<BlendMode>, SaveStateError> {
+        match self.u8()? {
+            0 => Ok(None),
+            value => match BlendMode::from_byte(value) {
+                Some(blend) => Ok(Some(blend)),
+                None => Err(SaveStateError::InvalidValue("blend mode")),
+            },
+        }
+    }
+
+    fn sprite_size(&mut self) -> Result<SpriteSize, SaveStateError> {
+        SpriteSize::from_byte(self.u8()?).ok_or(SaveStateError::InvalidValue("sprite size"))
+    }
+
+    fn affine(&mut self) -> Result<Affine, SaveStateError> {
+        Ok(Affine {
+            a: self.i32()?,
+            b: self.i32()?,
+            c: self.i32()?,
+            d: self.i32()?,
+            screen_center_x: self.i32()?,
+            screen_center_y: self.i32()?,
+            map_center_x: self.i32()?,
+            map_center_y: self.i32()?,
+            edge: match self.u8()? {
+                0 => AffineEdge::Wrap,
+                1 => AffineEdge::Clamp,
+                2 => AffineEdge::Transparent,
+                _ => return Err(SaveStateError::InvalidValue("affine edge")),
+            },
+        })
+    }
+
+    fn window(&mut self) -> Result<Window, SaveStateError> {
+        Ok(Window::from_bytes(self.array()?))
+    }
+
+    fn window_mask(&mut self) -> Result<WindowMask, SaveStateError> {
+        let mode =
+            WindowMode::from_byte(self.u8()?).ok_or(SaveStateError::InvalidValue("window mode"))?;
+
+        let mut mask = WindowMask {
+            mode,
+            ..Default::default()
+        };
+        for enabled in mask.windows.iter_mut() {
+            *enabled = self.bool()?;
+        }
+
+        Ok(mask)
+    }
+
+    fn register_write(&mut self) -> Result<RegisterWrite, SaveStateError> {
+        use RegisterWrite::*;
+
+        Ok(match self.u8()? {
+            0 => BgX(self.u8()?, self.u8()?),
+            1 => BgY(self.u8()?, self.u8()?),
+            2 => BgHidden(self.u8()?, self.bool()?),
+            3 => BgAffine(self.u8()?, self.option(Self::affine)?),
+            4 => OamHidden(self.bool()?),
+            5 => Window(self.u8()?, self.window()?),
+            6 => BackgroundColor(PaletteIndex(self.u8()?)),
+            7 => PaletteEntry(PaletteIndex(self.u8()?), self.rgb()?),
+            8 => Fade(self.fade()?),
+            _ => return Err(SaveStateError::InvalidValue("register write")),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scene() -> Vfc {
+        let mut fc = Vfc::with_config(VfcConfig::new().with_bg_size(16, 32)).unwrap();
+
+        fc.palette = Vfc::test_palette();
+        fc.background_color = PaletteIndex(3);
+        fc.fade = Fade::to_white(40);
+        fc.palette_animations = vec![
+            PaletteAnimation::rotate_subpalette(Subpalette::new(2), 3).reversed(),
+            PaletteAnimation::keyframes(
+                PaletteIndex(40),
+                vec![
+                    vec![Rgb::new(1, 2, 3)],
+                    vec![Rgb::new(4, 5, 6), Rgb::new(7, 8, 9)],
+                ],
+                2,
+            ),
+        ];
+        fc.object_limit = ObjectLimit::Flicker;
+        fc.oam_fade = Some(Fade::to_black(100));
+        fc.oam_window = WindowMask::inside(1);
+
+        for i in 0..16u8 {
+            let plane = [i.wrapping_mul(37); BYTES_PER_TILE_PLANE];
+            fc.tileset
+                .write_tile_in_bank(TileBank(i % 2), TileIndex(i), [plane; NUM_PLANES]);
+
+            fc.oam[OamIndex(i)] = OamEntry::new(
+                i * 11,
+                i * 9,
+                TileIndex(i),
+                TileAttributes::default()
+                    .with_palette(Subpalette::new(i % 8))
+                    .with_rotation(i % 8),
+            )
+            .with_size(SpriteSize::Size16x16)
+            .with_tile_bank(TileBank(i % 2));
+        }
+        fc.oam[OamIndex(3)].blend = Some(BlendMode::Average);
+
+        for (i, tile) in fc.bg_layers[0].tiles.iter_mut().enumerate() {
+            *tile = TileIndex(i as u8 % 16);
+        }
+        fc.bg_layers[0].attributes[5] = TileAttributes::default().with_priority(2);
+        fc.bg_layers[0].x = 12;
+        fc.bg_layers[1].affine = Some(Affine {
+            b: 40,
+            edge: AffineEdge::Clamp,
+            ..Affine::identity()
+        });
+        fc.bg_layers[1].blend = Some(BlendMode::Add);
+        fc.bg_layers[1].fade = Some(Fade::to_black(8));
+
+        fc.windows[1] = Window::new(10, 20, 100, 120);
+        fc.raster_table.push(40, RegisterWrite::BgX(0, 77));
+        fc.raster_table.push(
+            80,
+            RegisterWrite::PaletteEntry(PaletteIndex(5), Rgb::new(1, 2, 3)),
+        );
+        fc.raster_table.push(
+            80,
+            RegisterWrite::BgAffine(1, Some(Affine::rotate_scale(0.5, 2.0, (96, 80), (64, 64)))),
+        );
+
+        fc.render_frame();
+        fc
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut fc = scene();
+        let state = fc.save_state();
+
+        let mut loaded = Vfc::from_state(&state).unwrap();
+        assert_eq!(loaded.config(), fc.config());
+        assert_eq!(loaded.save_state(), state);
+
+        fc.render_frame();
+        loaded.render_frame();
+        assert!(loaded.framebuffer == fc.framebuffer);
+
+        // loading rewinds, but keeps the renderer
+        let mut rewound = scene();
+        rewound.renderer = Renderer::PerPixel;
+        rewound.bg_layers[0].x = 200;
+        rewound.load_state(&state).unwrap();
+        assert_eq!(rewound.renderer, Renderer::PerPixel);
+        rewound.render_frame();
+        assert!(rewound.framebuffer == fc.framebuffer);
+
+        // a palette shorter than the config's is saved wrapped around to the config's size
+        let mut short = scene();
+        short.palette = Palette::new(vec![Rgb::new(1, 2, 3), Rgb::new(4, 5, 6)]);
+        let loaded = Vfc::from_state(&short.save_state()).unwrap();
+        assert_eq!(loaded.palette.len(), short.config().num_palette_entries);
+        assert_eq!(loaded.palette[PaletteIndex(5)], Rgb::new(4, 5, 6));
+    }
+
+    #[test]
+    fn bad_states() {
+        let state = scene().save_state();
+
+        assert_eq!(
+            Vfc::from_state(b"nope").err(),
+            Some(SaveStateError::BadMagic)
+        );
+
+        let mut wrong_version = state.clone();
+        wrong_version[4] = 99;
+        assert_eq!(
+            Vfc::from_state(&wrong_version).err(),
+            Some(SaveStateError::UnsupportedVersion(99))
+        );
+
+        assert_eq!(
+            Vfc::from_state(&state[..state.len() - 1]).err(),
+            Some(SaveStateError::Truncated)
+        );
+
+        let mut too_long = state.clone();
+        too_long.push(0);
+        assert_eq!(
+            Vfc::from_state(&too_long).err(),
+            Some(SaveStateError::InvalidValue("length"))
+        );
+
+        // screen width
+        let mut bad_config = state.clone();
+        bad_config[6..8].copy_from_slice(&0u16.to_le_bytes());
+        assert!(matches!(
+            Vfc::from_state(&bad_config),
+            Err(SaveStateError::Config(ConfigError::ScreenSize { .. }))
+        ));
+
+        // oam and bg layer sizes that don't match the config, including one too big to allocate
+        let mut bad_oam = state.clone();
+        bad_oam[17..19].copy_from_slice(&3u16.to_le_bytes());
+        assert_eq!(
+            Vfc::from_state(&bad_oam).err(),
+            Some(SaveStateError::InvalidValue("oam size"))
+        );
+
+        // find the layer headers by their scroll
+        let mut fc = scene();
+        fc.bg_layers[0].x = 0xa0;
+        fc.bg_layers[0].y = 0xc0;
+        fc.bg_layers[1].x = 0xab;
+        fc.bg_layers[1].y = 0xcd;
+        fc.palette[PaletteIndex(0)] = Rgb::new(0xde, 0xad, 0xbe);
+        let state = fc.save_state();
+        let find_layer = |x: u8, y: u8| {
+            let header = [16u16.to_le_bytes(), 32u16.to_le_bytes(), [x, y]].concat();
+            state
+                .windows(header.len())
+                .position(|window| window == header)
+                .unwrap()
+        };
+        let layer_0 = find_layer(0xa0, 0xc0);
+        let layer_1 = find_layer(0xab, 0xcd);
+
+        // the layer count comes right before the first layer
+        let mut bad_count = state.clone();
+        bad_count[layer_0 - 1] = 255;
+        assert_eq!(
+            Vfc::from_state(&bad_count).err(),
+            Some(SaveStateError::InvalidValue("bg layer count"))
+        );
+
+        for (width, height) in [(3, 5), (u16::MAX, u16::MAX)] {
+            let mut bad_layer = state.clone();
+            bad_layer[layer_1..layer_1 + 2].copy_from_slice(&u16::to_le_bytes(width));
+            bad_layer[layer_1 + 2..layer_1 + 4].copy_from_slice(&u16::to_le_bytes(height));
+            assert_eq!(
+                Vfc::from_state(&bad_layer).err(),
+                Some(SaveStateError::InvalidValue("bg layer size"))
+            );
+        }
+
+        // a palette size that isn't the config's, or is past what a PaletteIndex reaches
+        let num_palette_entries = (fc.config().num_palette_entries as u32).to_le_bytes();
+        let palette_header = [&num_palette_entries[..], &[0xde, 0xad, 0xbe]].concat();
+        let palette = state
+            .windows(palette_header.len())
+            .position(|window| window == palette_header)
+            .unwrap();
+        for size in [3u32, 300] {
+            let mut bad_palette = state.clone();
+            bad_palette[palette..palette + 4].copy_from_slice(&size.to_le_bytes());
+            assert_eq!(
+                Vfc::from_state(&bad_palette).err(),
+                Some(SaveStateError::InvalidValue("palette size"))
+            );
+        }
+
+        // a failed load leaves the Vfc alone
+        let mut fc = scene();
+        fc.bg_layers[0].x = 1;
+        assert!(fc.load_state(&too_long).is_err());
+        assert_eq!(fc.bg_layers[0].x, 1);
+    }
+}
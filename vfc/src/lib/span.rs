@@ -0,0 +1,610 @@
+// the span renderer: tiles are decoded once into a cache, bg layers are drawn into line buffers
+// a tile row (an 8 pixel span) at a time, and then the whole line is composited.
+// the output is pixel-identical to the per-pixel renderer
+
+use crate::*;
+
+const TILE_PIXELS: usize = TILE_WIDTH * TILE_HEIGHT;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Renderer {
+    #[default]
+    Span,
+    // looks up every layer and sprite for every pixel. slow, but simple enough to check against
+    PerPixel,
+    // the span renderer on worker threads, a band of scanlines each. frames rendered with an
+    // hblank callback are drawn by Span instead, since the callback has to run between lines,
+    // and so is every frame when the "parallel" feature is off
+    Parallel,
+}
+
+// every tile in the tileset, decoded from bit planes to one color index per pixel
+pub(crate) struct TileCache {
+    // the bit planes the cache was decoded from, to spot tiles that changed
+    source: Box<[[[u8; BYTES_PER_TILE_PLANE]; NUM_TILES]; NUM_PLANES]>,
+    // the tileset generation it was decoded from, 0 before the first refresh
+    generation: u64,
+    pixels: Vec<[u8; TILE_PIXELS]>,
+}
+
+impl Default for TileCache {
+    // an all-zero tileset decodes to all-zero pixels, so this matches a new Tileset
+    fn default() -> Self {
+        Self {
+            source: Box::new([[[0; BYTES_PER_TILE_PLANE]; NUM_TILES]; NUM_PLANES]),
+            pixels: vec![[0; TILE_PIXELS]; NUM_TILES],
+            generation: 0,
+        }
+    }
+}
+
+impl TileCache {
+    // decode any tiles that changed since the last refresh. free when nothing was written
+    pub(crate) fn refresh(&mut self, tileset: &Tileset) {
+        if self.generation == tileset.generation() {
+            return;
+        }
+        self.generation = tileset.generation();
+
+        let pixel_data = tileset.pixel_data();
+
+        for tile in 0..NUM_TILES {
+            if (0..NUM_PLANES).all(|plane| self.source[plane][tile] == pixel_data[plane][tile]) {
+                continue;
+            }
+
+            for (source, plane) in self.source.iter_mut().zip(pixel_data) {
+                source[tile] = plane[tile];
+            }
+
+            self.pixels[tile] = decode_tile(pixel_data, tile);
+        }
+    }
+
+    fn pixel(&self, tileset_index: usize, pixel_x: u8, pixel_y: u8) -> u8 {
+        self.pixels[tileset_index][pixel_y as usize * TILE_WIDTH + pixel_x as usize]
+    }
+
+    // one row of an 8x8 tile the way it shows up on screen, flips and all
+    fn row(
+        &self,
+        tileset_index: usize,
+        attributes: &TileAttributes,
+        pixel_y: u8,
+    ) -> [u8; TILE_WIDTH] {
+        // the same flips as rotated_tile_pixel, for a whole row of one 8x8 tile
+        let pixels = &self.pixels[tileset_index];
+        let mut row = [0; TILE_WIDTH];
+
+        let pixel_y = pixel_y as usize;
+        let pixel_y = if attributes.get_flip_y() {
+            TILE_HEIGHT - 1 - pixel_y
+        } else {
+            pixel_y
+        };
+
+        if attributes.get_flip_diagonal() {
+            // a row on screen is a column of the tile
+            for (pixel_x, pixel) in row.iter_mut().enumerate() {
+                *pixel = pixels[pixel_x * TILE_WIDTH + pixel_y];
+            }
+        } else {
+            let start = pixel_y * TILE_WIDTH;
+            row.copy_from_slice(&pixels[start..start + TILE_WIDTH]);
+        }
+
+        if attributes.get_flip_x() {
+            row.reverse();
+        }
+
+        row
+    }
+}
+
+fn decode_tile(
+    pixel_data: &[[[u8; BYTES_PER_TILE_PLANE]; NUM_TILES]; NUM_PLANES],
+    tile: usize,
+) -> [u8; TILE_PIXELS] {
+    let mut pixels = [0; TILE_PIXELS];
+
+    for (i, pixel) in pixels.iter_mut().enumerate() {
+        let (pixel_x, pixel_y) = (i % TILE_WIDTH, i / TILE_WIDTH);
+
+        *pixel = (0..NUM_PLANES).fold(0, |acc, plane_index| {
+            (acc << 1) | ((pixel_data[plane_index][tile][pixel_y] >> pixel_x) & 1)
+        });
+    }
+
+    pixels
+}
+
+// a sprite's colorized pixel, and which sprite it came from
+type ObjectHit = (PaletteIndex, OamIndex);
+
+// scratch space for one line, reused from one scanline to the next
+#[derive(Default)]
+pub(crate) struct LineBuffers {
+    // each bg layer's pixels, None where the layer is transparent
+    bg: Vec<Vec<Option<PaletteIndex>>>,
+    // the first two opaque sprites on each pixel. the second one is what a translucent sprite
+    // blends with
+    oam: Vec<[Option<ObjectHit>; 2]>,
+}
+
+// where draw_line_spans puts a line, each buffer just the pixels of that line
+pub(crate) struct LineOutput<'a> {
+    pub(crate) framebuffer: &'a mut [Rgb],
+    pub(crate) indexed_framebuffer: Option<&'a mut [PaletteIndex]>,
+    pub(crate) pick_buffer: Option<&'a mut [Pick]>,
+}
+
+impl Vfc {
+    pub(crate) fn render_scanline_spans(
+        &mut self,
+        registers: &RasterRegisters,
+        line: &mut LineBuffers,
+        object_list: &[OamIndex],
+        yi: u8,
+    ) {
+        let start = self.get_fb_pixel_index(0, yi);
+        let pixels = start..start + self.config.screen_width;
+
+        // out of the way while the line is drawn, so the rest of the Vfc can be read
+        let mut framebuffer = std::mem::take(&mut self.framebuffer);
+        let mut indexed_framebuffer = self.indexed_framebuffer.take();
+        let mut pick_buffer = self.pick_buffer.take();
+
+        self.draw_line_spans(
+            registers,
+            line,
+            object_list,
+            yi,
+            LineOutput {
+                framebuffer: &mut framebuffer[pixels.clone()],
+                indexed_framebuffer: indexed_framebuffer
+                    .as_mut()
+                    .map(|indexed_framebuffer| &mut indexed_framebuffer[pixels.clone()]),
+                pick_buffer: pick_buffer
+                    .as_mut()
+                    .map(|pick_buffer| &mut pick_buffer[pixels]),
+            },
+        );
+
+        self.framebuffer = framebuffer;
+        self.indexed_framebuffer = indexed_framebuffer;
+        self.pick_buffer = pick_buffer;
+    }
+
+    // draw one line into `output`. everything a raster write can change is read from
+    // `registers` instead of the Vfc
+    pub(crate) fn draw_line_spans(
+        &self,
+        registers: &RasterRegisters,
+        line: &mut LineBuffers,
+        object_list: &[OamIndex],
+        yi: u8,
+        output: LineOutput,
+    ) {
+        let LineOutput {
+            framebuffer,
+            mut indexed_framebuffer,
+            mut pick_buffer,
+        } = output;
+
+        self.draw_bg_lines(registers, line, yi);
+        self.draw_oam_line(registers, line, object_list, yi);
+
+        let background = (LayerType::BgColor, registers.background_color);
+
+        for x in 0..self.config.screen_width {
+            // the top two opaque pixels. sprites are always in front of bg layers,
+            // and a translucent pixel blends with the one below it
+            let mut hits = [None, None];
+            let mut num_hits = 0;
+
+            for (palette_index, oam_index) in line.oam[x].iter().flatten() {
+                hits[num_hits] = Some((LayerType::Oam(*oam_index), *palette_index));
+                num_hits += 1;
+            }
+
+            for (layer_index, bg) in line.bg.iter().enumerate() {
+                if num_hits == hits.len() {
+                    break;
+                }
+
+                if let Some(palette_index) = bg[x] {
+                    hits[num_hits] = Some((LayerType::BgLayer(layer_index as u8), palette_index));
+                    num_hits += 1;
+                }
+            }
+
+            let [top, below] = hits;
+            let (layer, palette_index) = top.unwrap_or_else(|| background.clone());
+
+            let rgb = self.get_layer_rgb(&registers.palette, &layer, palette_index);
+
+            let rgb = match self.get_layer_blend(&layer) {
+                None => rgb,
+                Some(blend) => {
+                    let (below_layer, below_hit) = below.unwrap_or_else(|| background.clone());
+
+                    let below_rgb = self.get_layer_rgb(&registers.palette, &below_layer, below_hit);
+
+                    blend.blend(rgb, below_rgb)
+                }
+            };
+
+            framebuffer[x] = registers.fade.apply(rgb);
+
+            if let Some(indexed_framebuffer) = &mut indexed_framebuffer {
+                indexed_framebuffer[x] = palette_index;
+            }
+
+            if let Some(pick_buffer) = &mut pick_buffer {
+                pick_buffer[x] = self.get_pick(&layer, &registers.bg_layers, x as u8, yi);
+            }
+        }
+    }
+
+    fn draw_bg_lines(&self, registers: &RasterRegisters, line: &mut LineBuffers, yi: u8) {
+        let width = self.config.screen_width;
+        // layers past the last priority level never show, same as in get_top_pixel
+        let num_layers = self.bg_layers.len().min(NUM_OBJECT_PRIORITY_LEVELS);
+
+        line.bg.resize_with(num_layers, Vec::new);
+
+        let layers = self.bg_layers.iter().zip(registers.bg_layers.iter());
+
+        for ((layer, bg_registers), bg) in layers.zip(line.bg.iter_mut()) {
+            bg.clear();
+            bg.resize(width, None);
+
+            if bg_registers.hidden {
+                continue;
+            }
+
+            match &bg_registers.affine {
+                None => self.draw_bg_spans(layer, (bg_registers.x, bg_registers.y), bg, yi),
+                Some(affine) => self.draw_bg_affine(layer, affine, bg, yi),
+            }
+
+            if layer.window.mode != WindowMode::Off {
+                for (x, pixel) in bg.iter_mut().enumerate() {
+                    if !layer.window.allows(&registers.windows, x as u8, yi) {
+                        *pixel = None;
+                    }
+                }
+            }
+        }
+    }
+
+    // a scrolled layer, one tile row at a time
+    fn draw_bg_spans(
+        &self,
+        layer: &BgLayer,
+        (scroll_x, scroll_y): (u8, u8),
+        bg: &mut [Option<PaletteIndex>],
+        yi: u8,
+    ) {
+        let map_y = yi.wrapping_sub(scroll_y) as usize % layer.pixel_height();
+        let (tile_y, pixel_y) = (map_y / TILE_HEIGHT, (map_y % TILE_HEIGHT) as u8);
+
+        // maps are a power of two wide, so this wraps the same way the scroll does
+        let mut map_x = 0u8.wrapping_sub(scroll_x) as usize % layer.pixel_width();
+        let mut x = 0;
+
+        while x < bg.len() {
+            let (tile_x, first_pixel_x) = (map_x / TILE_WIDTH, map_x % TILE_WIDTH);
+
+            let map_index = layer.get_map_index(tile_x, tile_y);
+            let attributes = &layer.attributes[map_index];
+            let tile_index = layer.tile_bank.tileset_index(layer.tiles[map_index]);
+            let subpalette = attributes.get_palette();
+
+            let row = self.tile_cache.row(tile_index, attributes, pixel_y);
+
+            for &pixel in &row[first_pixel_x..] {
+                if x >= bg.len() {
+                    break;
+                }
+
+                if pixel != 0 {
+                    bg[x] = Some(subpalette.colorize_pixel(RawPixel(pixel)));
+                }
+
+                x += 1;
+            }
+
+            map_x = (map_x + TILE_WIDTH - first_pixel_x) % layer.pixel_width();
+        }
+    }
+
+    // a rotated and scaled layer, which has to be looked up a pixel at a time
+    fn draw_bg_affine(
+        &self,
+        layer: &BgLayer,
+        affine: &Affine,
+        bg: &mut [Option<PaletteIndex>],
+        yi: u8,
+    ) {
+        for (x, pixel) in bg.iter_mut().enumerate() {
+            let (map_x, map_y) =
+                match affine.map_pixel(x as u8, yi, layer.pixel_width(), layer.pixel_height()) {
+                    Some(map_pixel) => map_pixel,
+                    None => continue,
+                };
+
+            let map_index =
+                layer.get_map_index(map_x as usize / TILE_WIDTH, map_y as usize / TILE_HEIGHT);
+            let attributes = &layer.attributes[map_index];
+
+            let (tile_index, pixel_x, pixel_y) = match rotated_tile_pixel(
+                layer.tiles[map_index],
+                attributes,
+                SpriteSize::Size8x8,
+                map_x % TILE_WIDTH as u8,
+                map_y % TILE_HEIGHT as u8,
+            ) {
+                Some(tile_pixel) => tile_pixel,
+                None => continue,
+            };
+
+            let raw =
+                self.tile_cache
+                    .pixel(layer.tile_bank.tileset_index(tile_index), pixel_x, pixel_y);
+
+            if raw != 0 {
+                *pixel = Some(attributes.get_palette().colorize_pixel(RawPixel(raw)));
+            }
+        }
+    }
+
+    fn draw_oam_line(
+        &self,
+        registers: &RasterRegisters,
+        line: &mut LineBuffers,
+        object_list: &[OamIndex],
+        yi: u8,
+    ) {
+        let width = self.config.screen_width;
+
+        line.oam.clear();
+        line.oam.resize(width, [None, None]);
+
+        if registers.oam_hidden {
+            return;
+        }
+
+        // lower entries in the list win, so they go in first
+        for &oam_index in object_list {
+            let oam_entry = &self.oam[oam_index];
+            let local_y = yi.wrapping_sub(oam_entry.y);
+            let subpalette = oam_entry.attributes.get_palette();
+
+            for local_x in 0..oam_entry.width() {
+                let x = oam_entry.x.wrapping_add(local_x) as usize;
+
+                if x >= width || line.oam[x][1].is_some() {
+                    continue;
+                }
+
+                let (tile_index, pixel_x, pixel_y) = match rotated_tile_pixel(
+                    oam_entry.tile_index,
+                    &oam_entry.attributes,
+                    oam_entry.size,
+                    local_x,
+                    local_y,
+                ) {
+                    Some(tile_pixel) => tile_pixel,
+                    None => continue,
+                };
+
+                let raw = self.tile_cache.pixel(
+                    oam_entry.tile_bank.tileset_index(tile_index),
+                    pixel_x,
+                    pixel_y,
+                );
+
+                if raw == 0 || !self.oam_window.allows(&registers.windows, x as u8, yi) {
+                    continue;
+                }
+
+                let hit = Some((subpalette.colorize_pixel(RawPixel(raw)), oam_index));
+
+                match &mut line.oam[x] {
+                    [first @ None, _] => *first = hit,
+                    [_, second] => *second = hit,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a small lcg, so the scene is the same every run
+    struct Lcg(u32);
+
+    impl Lcg {
+        fn next(&mut self) -> u8 {
+            self.0 = self.0.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (self.0 >> 16) as u8
+        }
+    }
+
+    // every feature the renderers have to agree on, all at once
+    fn busy_scene() -> Vfc {
+        let mut rng = Lcg(7);
+        let mut fc = Vfc::new();
+        fc.enable_indexed_framebuffer();
+        fc.enable_pick_buffer();
+
+        fc.palette = Palette::new(
+            (0..NUM_PALETTE_ENTRIES)
+                .map(|_| Rgb::new(rng.next(), rng.next(), rng.next()))
+                .collect(),
+        );
+        fc.background_color = PaletteIndex(5);
+
+        // sparse tiles, so there is plenty of transparency to see through
+        for plane in fc.tileset.pixel_data_mut().iter_mut() {
+            for tile in plane.iter_mut() {
+                for row in tile.iter_mut() {
+                    *row = rng.next() & rng.next();
+                }
+            }
+        }
+
+        for layer in fc.bg_layers.iter_mut() {
+            for i in 0..layer.tiles.len() {
+                layer.tiles[i] = TileIndex(rng.next());
+                layer.attributes[i] = TileAttributes::default()
+                    .with_palette(Subpalette::new(rng.next()))
+                    .with_rotation(rng.next());
+            }
+        }
+        fc.bg_layers[0].x = 13;
+        fc.bg_layers[0].y = 250;
+        fc.bg_layers[0].tile_bank = TileBank(1);
+        fc.bg_layers[0].blend = Some(BlendMode::Average);
+        fc.bg_layers[0].window = WindowMask::outside(0);
+        fc.bg_layers[1].affine = Some(
+            Affine::rotate_scale(0.7, 1.3, (96, 80), (40, 200)).with_edge(AffineEdge::Transparent),
+        );
+        fc.bg_layers[1].fade = Some(Fade::to_white(90));
+
+        let sizes = [
+            SpriteSize::Size8x8,
+            SpriteSize::Size8x16,
+            SpriteSize::Size16x16,
+            SpriteSize::Size16x32,
+            SpriteSize::Size32x32,
+        ];
+        for i in 0..NUM_OAM_ENTRIES {
+            let mut entry = OamEntry::new(
+                rng.next(),
+                rng.next(),
+                TileIndex(rng.next()),
+                TileAttributes::default()
+                    .with_palette(Subpalette::new(rng.next()))
+                    .with_rotation(rng.next()),
+            )
+            .with_size(sizes[i % sizes.len()])
+            .with_tile_bank(TileBank(rng.next()));
+            if i % 5 == 0 {
+                entry = entry.with_blend(BlendMode::Add);
+            }
+            fc.oam.0[i] = entry;
+        }
+        fc.oam_fade = Some(Fade::to_black(40));
+        fc.oam_window = WindowMask::inside(1);
+        fc.object_limit = ObjectLimit::Flicker;
+
+        fc.windows[0] = Window::new(20, 30, 120, 90);
+        fc.windows[1] = Window::new(10, 0, 180, 150);
+        fc.fade = Fade::brightness(-30);
+
+        for scanline in (0..SCREEN_HEIGHT as u8).step_by(7) {
+            fc.raster_table
+                .push(scanline, RegisterWrite::BgX(0, scanline.wrapping_mul(3)));
+            fc.raster_table
+                .push(scanline, RegisterWrite::Fade(Fade::to_white(scanline / 2)));
+        }
+        fc.raster_table.push(100, RegisterWrite::OamHidden(true));
+        fc.raster_table.push(110, RegisterWrite::OamHidden(false));
+        fc.raster_table.push(120, RegisterWrite::BgHidden(1, true));
+
+        fc
+    }
+
+    fn render_frame(fc: &mut Vfc) -> (Vec<Rgb>, Vec<PaletteIndex>, Vec<Pick>) {
+        let frame = fc.frame_counter as u8;
+
+        // tiles written between lines have to show up on the lines below
+        fc.render_frame_with_hblank(|fc, scanline| {
+            if scanline == 64 {
+                let tile = [[frame ^ 0x5a; BYTES_PER_TILE_PLANE]; NUM_PLANES];
+                fc.tileset
+                    .write_tile_in_bank(TileBank(1), TileIndex(0), tile);
+            }
+        });
+
+        (
+            fc.framebuffer.clone(),
+            fc.indexed_framebuffer.clone().unwrap(),
+            fc.pick_buffer.clone().unwrap(),
+        )
+    }
+
+    #[test]
+    fn span_renderer_matches_per_pixel() {
+        let mut per_pixel = busy_scene();
+        per_pixel.renderer = Renderer::PerPixel;
+        let mut span = busy_scene();
+
+        for frame in 0..4 {
+            for fc in [&mut per_pixel, &mut span] {
+                fc.bg_layers[0].x = fc.bg_layers[0].x.wrapping_add(37);
+                fc.oam.0[3].x = fc.oam.0[3].x.wrapping_sub(5);
+            }
+
+            assert!(
+                render_frame(&mut per_pixel) == render_frame(&mut span),
+                "frame {frame} differs"
+            );
+        }
+
+        // a whole new tileset reaches the tile cache too
+        for fc in [&mut per_pixel, &mut span] {
+            fc.tileset = Tileset::default();
+            fc.tileset.pixel_data_mut()[0][TileBank(1).tileset_index(TileIndex(7))] =
+                [0xff; BYTES_PER_TILE_PLANE];
+        }
+        assert!(render_frame(&mut per_pixel) == render_frame(&mut span));
+    }
+
+    // without the "parallel" feature this checks the fallback to Span
+    #[test]
+    fn parallel_renderer_matches_serial() {
+        let mut serial = busy_scene();
+        let mut parallel = busy_scene();
+        parallel.renderer = Renderer::Parallel;
+
+        for frame in 0..4 {
+            for fc in [&mut serial, &mut parallel] {
+                fc.bg_layers[0].x = fc.bg_layers[0].x.wrapping_add(37);
+                fc.oam.0[3].x = fc.oam.0[3].x.wrapping_sub(5);
+                fc.tileset.write_tile(
+                    TileIndex(frame),
+                    [[frame; BYTES_PER_TILE_PLANE]; NUM_PLANES],
+                );
+                fc.render_frame();
+            }
+
+            assert!(
+                serial.framebuffer == parallel.framebuffer,
+                "frame {frame} differs"
+            );
+            assert!(serial.indexed_framebuffer == parallel.indexed_framebuffer);
+            assert!(serial.pick_buffer == parallel.pick_buffer);
+            assert_eq!(serial.object_overflow.lines, parallel.object_overflow.lines);
+            assert_eq!(serial.bg_layers[0].x, parallel.bg_layers[0].x);
+            assert_eq!(serial.frame_counter, parallel.frame_counter);
+        }
+
+        // without the indexed framebuffer and pick buffer, and with an hblank callback, which falls back to Span
+        serial.disable_indexed_framebuffer();
+        parallel.disable_indexed_framebuffer();
+        serial.disable_pick_buffer();
+        parallel.disable_pick_buffer();
+        serial.render_frame();
+        parallel.render_frame();
+        assert!(serial.framebuffer == parallel.framebuffer);
+
+        serial.render_frame_with_hblank(|fc, scanline| fc.bg_layers[1].hidden = scanline > 50);
+        parallel.render_frame_with_hblank(|fc, scanline| fc.bg_layers[1].hidden = scanline > 50);
+        assert!(serial.framebuffer == parallel.framebuffer);
+    }
+}
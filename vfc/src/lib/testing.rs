@@ -0,0 +1,302 @@
+// headless rendering and golden image comparison, for tests
+
+use std::path::{Path, PathBuf};
+
+use image::RgbImage;
+
+use crate::*;
+
+// set this environment variable to (re)write golden images instead of comparing against them
+pub const BLESS_VAR: &str = "VFC_BLESS";
+
+#[derive(Debug)]
+pub enum GoldenError {
+    Image(image::ImageError),
+    Missing(PathBuf),
+    SizeMismatch {
+        expected: (u32, u32),
+        found: (u32, u32),
+    },
+    PixelMismatch {
+        differing_pixels: usize,
+        first: (u32, u32),
+        diff_path: PathBuf,
+    },
+}
+
+impl std::fmt::Display for GoldenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            GoldenError::Image(e) => write!(f, "{e}"),
+            GoldenError::Missing(path) => write!(
+                f,
+                "golden image {} does not exist, run with {BLESS_VAR}=1 to create it",
+                path.display()
+            ),
+            GoldenError::SizeMismatch { expected, found } => write!(
+                f,
+                "golden image is {}x{} but the framebuffer is {}x{}",
+                expected.0, expected.1, found.0, found.1
+            ),
+            GoldenError::PixelMismatch {
+                differing_pixels,
+                first,
+                diff_path,
+            } => write!(
+                f,
+                "{differing_pixels} pixels differ (first at {}, {}), see {}",
+                first.0,
+                first.1,
+                diff_path.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GoldenError {}
+
+impl From<image::ImageError> for GoldenError {
+    fn from(e: image::ImageError) -> GoldenError {
+        GoldenError::Image(e)
+    }
+}
+
+// render `frames` frames with no window, calling `update` before each one
+pub fn run_frames<F>(fc: &mut Vfc, frames: usize, mut update: F)
+where
+    F: FnMut(&mut Vfc, usize),
+{
+    for frame in 0..frames {
+        update(fc, frame);
+        fc.render_frame();
+    }
+}
+
+pub fn framebuffer_to_image(fc: &Vfc) -> RgbImage {
+    let config = fc.config();
+
+    RgbImage::from_fn(
+        config.screen_width as u32,
+        config.screen_height as u32,
+        |x, y| {
+            let rgb = fc.framebuffer[fc.get_fb_pixel_index(x as u8, y as u8)];
+            let [_a, r, g, b] = rgb.as_argb_u32().to_be_bytes();
+            image::Rgb([r, g, b])
+        },
+    )
+}
+
+// compare the current framebuffer against a png on disk
+//
+// on a mismatch, `<name>.actual.png` and `<name>.diff.png` are written next to the golden image.
+// the diff shows differing pixels in red over a darkened copy of the actual frame.
+pub fn compare_golden<P: AsRef<Path>>(fc: &Vfc, path: P) -> Result<(), GoldenError> {
+    let path = path.as_ref();
+
+    if !path.exists() {
+        return Err(GoldenError::Missing(path.to_path_buf()));
+    }
+
+    let expected = image::open(path)?.into_rgb8();
+    let actual = framebuffer_to_image(fc);
+
+    if expected.dimensions() != actual.dimensions() {
+        return Err(GoldenError::SizeMismatch {
+            expected: expected.dimensions(),
+            found: actual.dimensions(),
+        });
+    }
+
+    let mut differing_pixels = 0;
+    let mut first = None;
+
+    let diff = RgbImage::from_fn(actual.width(), actual.height(), |x, y| {
+        let a = actual.get_pixel(x, y);
+
+        if a == expected.get_pixel(x, y) {
+            image::Rgb(a.0.map(|channel| channel / 4))
+        } else {
+            differing_pixels += 1;
+            first.get_or_insert((x, y));
+            image::Rgb([255, 0, 0])
+        }
+    });
+
+    let first = match first {
+        Some(first) => first,
+        None => return Ok(()),
+    };
+
+    let diff_path = path.with_extension("diff.png");
+
+    actual.save(path.with_extension("actual.png"))?;
+    diff.save(&diff_path)?;
+
+    Err(GoldenError::PixelMismatch {
+        differing_pixels,
+        first,
+        diff_path,
+    })
+}
+
+// panic unless the framebuffer matches the golden image at `path`
+// with `VFC_BLESS` set, the golden image is written instead
+pub fn assert_golden<P: AsRef<Path>>(fc: &Vfc, path: P) {
+    let path = path.as_ref();
+
+    if std::env::var_os(BLESS_VAR).is_some() {
+        framebuffer_to_image(fc)
+            .save(path)
+            .unwrap_or_else(|e| panic!("could not write {}: {e}", path.display()));
+        return;
+    }
+
+    if let Err(e) = compare_golden(fc, path) {
+        panic!("{}: {e}", path.display());
+    }
+}
+
+// pack a tile given as rows of color indices into bit planes, for building test tilesets
+pub fn planar_tile(
+    rows: &[[u8; TILE_WIDTH]; TILE_HEIGHT],
+) -> [[u8; BYTES_PER_TILE_PLANE]; NUM_PLANES] {
+    let mut tile = [[0; BYTES_PER_TILE_PLANE]; NUM_PLANES];
+
+    for (pixel_y, row) in rows.iter().enumerate() {
+        for (pixel_x, color_index) in row.iter().enumerate() {
+            for (plane_index, plane) in tile.iter_mut().enumerate() {
+                let bit = (color_index >> (NUM_PLANES - 1 - plane_index)) & 1;
+                plane[pixel_y] |= bit << pixel_x;
+            }
+        }
+    }
+
+    tile
+}
+
+// an asymmetric glyph so every flip and rotation looks different
+pub const GLYPH: [[u8; TILE_WIDTH]; TILE_HEIGHT] = [
+    [1, 1, 1, 1, 1, 1, 0, 0],
+    [1, 2, 2, 2, 2, 1, 0, 0],
+    [1, 2, 0, 0, 0, 0, 0, 0],
+    [1, 2, 3, 3, 3, 0, 0, 0],
+    [1, 2, 3, 4, 0, 0, 0, 0],
+    [1, 2, 0, 0, 0, 0, 0, 0],
+    [1, 2, 0, 0, 0, 0, 5, 0],
+    [1, 1, 0, 0, 0, 0, 0, 7],
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHECKER: [[u8; TILE_WIDTH]; TILE_HEIGHT] = [
+        [6, 6, 6, 6, 0, 0, 0, 0],
+        [6, 6, 6, 6, 0, 0, 0, 0],
+        [6, 6, 6, 6, 0, 0, 0, 0],
+        [6, 6, 6, 6, 0, 0, 0, 0],
+        [0, 0, 0, 0, 6, 6, 6, 6],
+        [0, 0, 0, 0, 6, 6, 6, 6],
+        [0, 0, 0, 0, 6, 6, 6, 6],
+        [0, 0, 0, 0, 6, 6, 6, 6],
+    ];
+
+    fn golden_path(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("golden")
+            .join(name)
+            .with_extension("png")
+    }
+
+    fn test_vfc() -> Vfc {
+        let mut fc = Vfc::new();
+
+        fc.palette = Palette::new(
+            (0..NUM_PALETTE_ENTRIES)
+                .map(|i| {
+                    let i = i as u8;
+                    Rgb::new(
+                        i.wrapping_mul(37),
+                        i.wrapping_mul(91),
+                        i.wrapping_mul(13) ^ 0x80,
+                    )
+                })
+                .collect(),
+        );
+
+        fc.tileset.write_tile(TileIndex(1), planar_tile(&GLYPH));
+        fc.tileset.write_tile(TileIndex(2), planar_tile(&CHECKER));
+
+        fc
+    }
+
+    #[test]
+    fn golden_sprite_flips() {
+        let mut fc = test_vfc();
+
+        for rotation in 0..8 {
+            fc.oam.0[rotation as usize] = OamEntry::new(
+                16 + rotation * 12,
+                32,
+                TileIndex(1),
+                TileAttributes::default()
+                    .with_rotation(rotation)
+                    .with_palette(Subpalette::new(rotation % 2)),
+            );
+        }
+
+        run_frames(&mut fc, 1, |_, _| {});
+
+        assert_golden(&fc, golden_path("sprite_flips"));
+    }
+
+    #[test]
+    fn golden_priority() {
+        let mut fc = test_vfc();
+
+        for i in 0..NUM_BG_TILES {
+            fc.bg_layers[0].tiles[i] = TileIndex(2);
+            fc.bg_layers[1].tiles[i] = TileIndex((i % 3 == 0) as u8);
+            fc.bg_layers[1].attributes[i].set_palette(Subpalette::new(2));
+        }
+
+        // overlapping sprites, one per priority level, plus one hanging off the top left corner
+        for priority in 0..NUM_OBJECT_PRIORITY_LEVELS as u8 {
+            fc.oam.0[priority as usize] = OamEntry::new(
+                40 + priority * 3,
+                40 + priority * 3,
+                TileIndex(1),
+                TileAttributes::default()
+                    .with_priority(priority)
+                    .with_palette(Subpalette::new(3 + priority)),
+            );
+        }
+        fc.oam.0[4] = OamEntry::new(0xfc, 0xfd, TileIndex(1), TileAttributes::default());
+
+        run_frames(&mut fc, 1, |_, _| {});
+
+        assert_golden(&fc, golden_path("priority"));
+    }
+
+    #[test]
+    fn golden_scrolling() {
+        let mut fc = test_vfc();
+
+        for i in 0..NUM_BG_TILES {
+            let (x, y) = (i % BG_WIDTH, i / BG_WIDTH);
+            fc.bg_layers[0].tiles[i] = TileIndex(1 + ((x + y) % 2) as u8);
+            fc.bg_layers[0].attributes[i].set_palette(Subpalette::new((x % 4) as u8));
+            fc.bg_layers[1].tiles[i] = TileIndex((x % 5 == 0 && y % 3 == 0) as u8);
+            fc.bg_layers[1].attributes[i].set_palette(Subpalette::new(5));
+        }
+
+        // scroll far enough that both layers wrap around the 256x256 map
+        run_frames(&mut fc, 20, |fc, _| {
+            fc.bg_layers[0].x = fc.bg_layers[0].x.wrapping_sub(13);
+            fc.bg_layers[0].y = fc.bg_layers[0].y.wrapping_add(7);
+            fc.bg_layers[1].x = fc.bg_layers[1].x.wrapping_add(3);
+        });
+
+        assert_golden(&fc, golden_path("scrolling"));
+    }
+}
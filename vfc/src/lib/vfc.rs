@@ -1,25 +1,82 @@
 //
+mod affine;
+mod blend;
+mod bus;
+mod collision;
+mod config;
 mod constants;
+mod fade;
 mod oam;
-
+mod palette_animation;
+mod palette_file;
+#[cfg(feature = "parallel")]
+mod parallel;
+mod pick;
+mod raster;
+mod savestate;
+mod span;
+mod window;
+
+pub mod debug;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
+pub use affine::*;
+pub use blend::*;
+pub use bus::*;
+pub use collision::*;
+pub use config::*;
+pub use fade::*;
 pub use oam::*;
+pub use palette_animation::*;
+pub use palette_file::*;
+pub use pick::*;
+pub use raster::*;
+pub use savestate::*;
+pub use span::*;
+pub use window::*;
 
 pub use constants::*;
 
+use std::sync::atomic::{AtomicU64, Ordering};
+
 pub struct Vfc {
     // stuff goes here
-    pub framebuffer: [Rgb; NUM_SCREEN_PIXELS],
-    //~ pub indexed_framebuffer: [PaletteIndex; NUM_SCREEN_PIXELS],
+    // fixed when the Vfc is built, everything below is sized to match
+    config: VfcConfig,
+    pub framebuffer: Vec<Rgb>,
+    // palette indices of the last frame, before palette lookup. only kept when enabled
+    pub indexed_framebuffer: Option<Vec<PaletteIndex>>,
+    // which sprite or bg tile each pixel of the last frame came from. only kept when enabled
+    pub pick_buffer: Option<Vec<Pick>>,
     pub oam: OamTable,
     pub oam_hidden: bool,
+    pub oam_window: WindowMask,
+    pub oam_fade: Option<Fade>,
+    pub object_limit: ObjectLimit,
+    pub object_overflow: ObjectOverflow,
+    // which sprites touched each other or the background in the last frame. only kept when enabled
+    pub collisions: Option<Collisions>,
     //~ sorted_objects: [[Option<OamIndex>; OBJECTS_PER_LINE]; SCREEN_HEIGHT],
     pub palette: Palette,
+    // advanced at the end of every frame
+    pub palette_animations: Vec<PaletteAnimation>,
     pub background_color: PaletteIndex,
+    // master brightness, applied to the whole screen after everything else
+    pub fade: Fade,
     pub tileset: Tileset,
-    pub bg_layers: [BgLayer; NUM_BG_LAYERS],
+    pub bg_layers: Vec<BgLayer>,
+    pub windows: [Window; NUM_WINDOWS],
+    pub raster_table: RasterTable,
+    // counts up once per rendered frame
+    pub frame_counter: usize,
+    pub renderer: Renderer,
+    tile_cache: TileCache,
 }
 
-#[derive(Debug, Default, Clone)]
+type HblankCallback<'a> = &'a mut dyn FnMut(&mut Vfc, u8);
+
+#[derive(Debug, Default, Clone, PartialEq)]
 pub enum LayerType {
     #[default]
     BgColor,
@@ -48,14 +105,30 @@ impl std::ops::Index<TileIndex> for Tileset {
 #[derive(Debug, Default, Clone, Copy, PartialEq)]
 pub struct RawPixel(pub u8);
 
+// palette indices past the end wrap around, so smaller palettes just repeat
 #[repr(transparent)]
 #[derive(Clone)]
-pub struct Palette([Rgb; NUM_PALETTE_ENTRIES]);
+pub struct Palette(Vec<Rgb>);
 
 impl Palette {
-    pub fn new(p: [Rgb; NUM_PALETTE_ENTRIES]) -> Palette {
+    // indices wrap around the palette, so it needs at least one colour
+    pub fn new(p: Vec<Rgb>) -> Palette {
+        assert!(!p.is_empty(), "a palette needs at least one colour");
         Palette(p)
     }
+
+    pub fn with_size(num_entries: usize) -> Palette {
+        assert!(num_entries > 0, "a palette needs at least one colour");
+        Palette(vec![Rgb::default(); num_entries])
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
 }
 
 #[repr(transparent)]
@@ -76,7 +149,20 @@ impl std::ops::Index<PaletteIndex> for Palette {
     type Output = Rgb;
 
     fn index(&self, index: PaletteIndex) -> &Self::Output {
-        &self.0[index.0 as usize]
+        let index = index.0 as usize;
+
+        if index < self.0.len() {
+            &self.0[index]
+        } else {
+            &self.0[index % self.0.len()]
+        }
+    }
+}
+
+impl std::ops::IndexMut<PaletteIndex> for Palette {
+    fn index_mut(&mut self, index: PaletteIndex) -> &mut Self::Output {
+        let len = self.0.len();
+        &mut self.0[index.0 as usize % len]
     }
 }
 
@@ -93,7 +179,7 @@ impl Subpalette {
         &self.0
     }
 
-    fn colorize_pixel(&self, pixel: RawPixel) -> PaletteIndex {
+    pub fn colorize_pixel(&self, pixel: RawPixel) -> PaletteIndex {
         PaletteIndex(
             self.0
                 .wrapping_mul(SUBPALETTE_SIZE as u8)
@@ -136,6 +222,18 @@ impl Rgb {
     pub fn as_argb_u32(&self) -> u32 {
         self.0
     }
+
+    pub fn r(&self) -> u8 {
+        self.0.to_be_bytes()[1]
+    }
+
+    pub fn g(&self) -> u8 {
+        self.0.to_be_bytes()[2]
+    }
+
+    pub fn b(&self) -> u8 {
+        self.0.to_be_bytes()[3]
+    }
 }
 
 // FIXME: why is there rotation data here what the hell
@@ -143,13 +241,35 @@ impl Rgb {
 // NOTE: why the hell is this a PaletteIndex? answer: it's 8 PaletteIndex bits packed into a byte
 // so like it's probably fine idk. probably better without tho
 pub struct Tileset {
-    pub pixel_data: [[[u8; BYTES_PER_TILE_PLANE]; NUM_TILES]; NUM_PLANES],
+    pixel_data: [[[u8; BYTES_PER_TILE_PLANE]; NUM_TILES]; NUM_PLANES],
+    // a new number every time the tiles may have changed, so the renderer's tile cache can
+    // skip comparing the whole tileset when nothing was written
+    generation: u64,
+}
+
+// shared by every tileset, so a tileset swapped in never has the generation of the old one
+static NEXT_TILESET_GENERATION: AtomicU64 = AtomicU64::new(1);
+
+fn next_tileset_generation() -> u64 {
+    NEXT_TILESET_GENERATION.fetch_add(1, Ordering::Relaxed)
 }
 
 #[repr(transparent)]
 #[derive(Debug, Default, Clone, Copy, PartialEq)]
 pub struct TileIndex(pub u8);
 
+// selects which group of 256 tiles a TileIndex refers to
+#[repr(transparent)]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct TileBank(pub u8);
+
+impl TileBank {
+    // index into the whole tileset
+    pub fn tileset_index(&self, tile_index: TileIndex) -> usize {
+        (self.0 as usize % NUM_TILE_BANKS) * TILES_PER_BANK + tile_index.0 as usize
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Tile<'a> {
     pub tile: [&'a [u8; BYTES_PER_TILE_PLANE]; NUM_PLANES],
@@ -157,10 +277,20 @@ pub struct Tile<'a> {
 
 #[derive(Debug)]
 pub struct BgLayer {
+    // map size in tiles
+    width: usize,
+    height: usize,
     pub x: u8,
     pub y: u8,
-    pub tiles: [TileIndex; NUM_BG_TILES],
-    pub attributes: [TileAttributes; NUM_BG_TILES],
+    pub tiles: Vec<TileIndex>,
+    pub attributes: Vec<TileAttributes>,
+    pub tile_bank: TileBank,
+    // draw the layer rotated and scaled instead of scrolled
+    pub affine: Option<Affine>,
+    pub window: WindowMask,
+    // makes the layer translucent, mixing it with whatever is below
+    pub blend: Option<BlendMode>,
+    pub fade: Option<Fade>,
     pub hidden: bool,
 }
 
@@ -174,6 +304,16 @@ impl TileAttributes {
         s
     }
 
+    // the byte the bus, save states and map exports use: palette in bits 0-2, rotation in
+    // bits 3-5, priority in bits 6-7
+    pub fn to_byte(&self) -> u8 {
+        self.0
+    }
+
+    pub fn from_byte(value: u8) -> TileAttributes {
+        TileAttributes(value)
+    }
+
     // build
 
     pub fn with_palette(mut self, subpalette: Subpalette) -> TileAttributes {
@@ -310,30 +450,150 @@ impl Tileset {
         tile_index: TileIndex,
         tile: [[u8; BYTES_PER_TILE_PLANE]; NUM_PLANES],
     ) {
+        self.write_tile_in_bank(TileBank(0), tile_index, tile);
+    }
+
+    pub fn write_tile_in_bank(
+        &mut self,
+        bank: TileBank,
+        tile_index: TileIndex,
+        tile: [[u8; BYTES_PER_TILE_PLANE]; NUM_PLANES],
+    ) {
+        let index = bank.tileset_index(tile_index);
+
         for (plane_index, plane) in tile.iter().enumerate() {
-            self.pixel_data[plane_index][tile_index.0 as usize] = *plane;
+            self.pixel_data_mut()[plane_index][index] = *plane;
+        }
+    }
+
+    pub fn pixel_data(&self) -> &[[[u8; BYTES_PER_TILE_PLANE]; NUM_TILES]; NUM_PLANES] {
+        &self.pixel_data
+    }
+
+    pub fn pixel_data_mut(&mut self) -> &mut [[[u8; BYTES_PER_TILE_PLANE]; NUM_TILES]; NUM_PLANES] {
+        self.generation = next_tileset_generation();
+        &mut self.pixel_data
+    }
+
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    // a pixel of a tile, in local coords
+    pub fn get_pixel(
+        &self,
+        bank: TileBank,
+        tile_index: TileIndex,
+        pixel_x: u8,
+        pixel_y: u8,
+    ) -> RawPixel {
+        let tile_index = bank.tileset_index(tile_index);
+
+        let pixel = (0..NUM_PLANES).fold(0, |acc, plane_index| {
+            (acc << 1)
+                | ((self.pixel_data[plane_index][tile_index][pixel_y as usize] as usize >> pixel_x)
+                    & 1)
+        });
+
+        RawPixel(pixel as u8)
+    }
+
+    // a pixel of an 8x8 tile drawn with the flips of `attributes`, like a bg layer draws it
+    pub fn get_pixel_rotated(
+        &self,
+        bank: TileBank,
+        tile_index: TileIndex,
+        attributes: &TileAttributes,
+        pixel_x: u8,
+        pixel_y: u8,
+    ) -> RawPixel {
+        match rotated_tile_pixel(
+            tile_index,
+            attributes,
+            SpriteSize::Size8x8,
+            pixel_x,
+            pixel_y,
+        ) {
+            Some((tile_index, pixel_x, pixel_y)) => {
+                self.get_pixel(bank, tile_index, pixel_x, pixel_y)
+            }
+            None => RawPixel(0),
         }
     }
 }
 
 impl BgLayer {
-    fn get_tile_index(&self, tile_x: u8, tile_y: u8) -> TileIndex {
-        let tile_x = tile_x as usize;
-        let tile_y = tile_y as usize;
+    // an empty map of `width` by `height` tiles
+    pub fn new(width: usize, height: usize) -> BgLayer {
+        BgLayer {
+            width,
+            height,
+            tiles: vec![TileIndex::default(); width * height],
+            attributes: vec![TileAttributes::default(); width * height],
+            tile_bank: TileBank::default(),
+            affine: None,
+            window: WindowMask::default(),
+            blend: None,
+            fade: None,
+            x: 0,
+            y: 0,
+            hidden: false,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixel_width(&self) -> usize {
+        self.width * TILE_WIDTH
+    }
+
+    pub fn pixel_height(&self) -> usize {
+        self.height * TILE_HEIGHT
+    }
+
+    // index into `tiles` and `attributes`, wrapping around the edges of the map
+    pub fn get_map_index(&self, tile_x: usize, tile_y: usize) -> usize {
+        (tile_y % self.height) * self.width + tile_x % self.width
+    }
+
+    // the map pixel under a screen pixel, for the given scroll or affine registers.
+    // None if it falls outside a transparent-edged affine map
+    pub(crate) fn map_pixel_at(
+        &self,
+        (scroll_x, scroll_y): (u8, u8),
+        affine: Option<&Affine>,
+        screen_x: u8,
+        screen_y: u8,
+    ) -> Option<(u8, u8)> {
+        match affine {
+            // maps are a power of two wide, so this wraps the same way the scroll does
+            None => Some((
+                (screen_x.wrapping_sub(scroll_x) as usize % self.pixel_width()) as u8,
+                (screen_y.wrapping_sub(scroll_y) as usize % self.pixel_height()) as u8,
+            )),
+            Some(affine) => {
+                affine.map_pixel(screen_x, screen_y, self.pixel_width(), self.pixel_height())
+            }
+        }
+    }
 
+    fn get_tile_index(&self, tile_x: u8, tile_y: u8) -> TileIndex {
         // this one was a fun bug to track down
         //~ self.tiles[tile_x.wrapping_add(tile_y.wrapping_mul(TILE_SIZE as u8)) as usize]
 
-        self.tiles[tile_x.wrapping_add(tile_y.wrapping_mul(BG_WIDTH)) as usize]
+        self.tiles[self.get_map_index(tile_x as usize, tile_y as usize)]
 
         //~ self.tiles[tile_x as usize + tile_y as usize * BG_WIDTH as usize]
     }
 
     fn get_tile_attribute(&self, tile_x: u8, tile_y: u8) -> &TileAttributes {
-        let tile_x = tile_x as usize;
-        let tile_y = tile_y as usize;
-
-        &self.attributes[tile_x.wrapping_add(tile_y.wrapping_mul(BG_WIDTH)) as usize]
+        &self.attributes[self.get_map_index(tile_x as usize, tile_y as usize)]
     }
 }
 
@@ -342,6 +602,46 @@ impl Vfc {
         Vfc { ..Vfc::default() }
     }
 
+    // a Vfc with its own screen size, layer count, map size, oam size and palette size
+    pub fn with_config(config: VfcConfig) -> Result<Vfc, ConfigError> {
+        config.validate()?;
+
+        Ok(Vfc::build(config))
+    }
+
+    fn build(config: VfcConfig) -> Vfc {
+        Vfc {
+            config,
+            oam: OamTable::new(config.num_oam_entries),
+            oam_hidden: false,
+            oam_window: WindowMask::default(),
+            oam_fade: None,
+            object_limit: ObjectLimit::default(),
+            object_overflow: ObjectOverflow::new(config.screen_height),
+            collisions: None,
+            framebuffer: vec![Rgb::default(); config.num_screen_pixels()],
+            indexed_framebuffer: None,
+            pick_buffer: None,
+            palette: Palette::with_size(config.num_palette_entries),
+            palette_animations: vec![],
+            background_color: PaletteIndex::default(),
+            fade: Fade::default(),
+            tileset: Tileset::new(),
+            bg_layers: (0..config.num_bg_layers)
+                .map(|_| BgLayer::new(config.bg_width, config.bg_height))
+                .collect(),
+            windows: Default::default(),
+            raster_table: RasterTable::default(),
+            frame_counter: 0,
+            renderer: Renderer::default(),
+            tile_cache: TileCache::default(),
+        }
+    }
+
+    pub fn config(&self) -> &VfcConfig {
+        &self.config
+    }
+
     pub fn test_palette() -> Palette {
         Palette(
             [(); 256]
@@ -353,39 +653,173 @@ impl Vfc {
                     //~ Rgb::new(x ^ y, 0, 0)
                     Rgb::new(x * 16 as u8, y * 16 as u8, i as u8)
                 })
-                .collect::<Vec<_>>()
-                .try_into()
-                .unwrap_or_else(|_| unreachable!()),
+                .collect(),
         )
     }
 
     pub fn render_frame(&mut self) {
-        //~ let object_list = &mut Default::default();
+        self.render_frame_inner(None);
+    }
 
-        for scanline in 0..SCREEN_HEIGHT as u8 {
-            //~ let object_list = &mut Default::default();
+    // render a frame, calling `hblank` before each scanline so it can change registers mid-frame.
+    // the writes in `raster_table` are applied first, and undone once the frame is finished.
+    // what `hblank` writes isn't undone, the registers stay as it left them after the last line,
+    // except for registers the raster table also writes: the raster table wins, and those go
+    // back to how they were before the frame
+    pub fn render_frame_with_hblank<F>(&mut self, mut hblank: F)
+    where
+        F: FnMut(&mut Vfc, u8),
+    {
+        self.render_frame_inner(Some(&mut hblank));
+    }
+
+    fn render_frame_inner(&mut self, mut hblank: Option<HblankCallback>) {
+        #[cfg(feature = "parallel")]
+        if self.renderer == Renderer::Parallel && hblank.is_none() {
+            self.render_frame_parallel();
+            return;
+        }
+
+        let saved_registers = if self.raster_table.is_empty() {
+            None
+        } else {
+            Some(RasterRegisters::save(self))
+        };
+
+        self.object_overflow.clear();
+
+        if let Some(collisions) = &mut self.collisions {
+            collisions.clear();
+        }
+
+        self.tile_cache.refresh(&self.tileset);
+
+        let mut line_buffers = LineBuffers::default();
+
+        // what the span renderer draws with, kept up to date with raster writes and hblank
+        let mut registers = RasterRegisters::save(self);
+
+        for scanline in 0..self.config.screen_height {
+            let scanline = scanline as u8;
+
+            let has_raster_writes = !self.raster_table.get(scanline).is_empty();
+
+            self.apply_raster_writes(scanline);
+
+            if let Some(hblank) = &mut hblank {
+                hblank(self, scanline);
+
+                // it may have written tiles
+                self.tile_cache.refresh(&self.tileset);
+            }
+
+            if has_raster_writes || hblank.is_some() {
+                registers = RasterRegisters::save(self);
+            }
 
             let object_list = self.get_objects_on_scanline(scanline);
 
-            //~ self.get_objects_on_scanline_buffered(object_list, scanline);
+            let object_list = self.limit_objects_on_scanline(object_list, scanline);
+
+            self.detect_collisions(&object_list, scanline);
+
+            match self.renderer {
+                Renderer::PerPixel => self.render_scanline(&object_list[..], scanline),
+                _ => self.render_scanline_spans(
+                    &registers,
+                    &mut line_buffers,
+                    &object_list[..],
+                    scanline,
+                ),
+            }
+        }
 
-            self.render_scanline(&object_list[..], scanline);
+        if let Some(registers) = saved_registers {
+            self.undo_raster_writes(&registers);
         }
+
+        self.finish_frame();
+    }
+
+    // everything that happens once a frame is done, after raster writes are undone
+    pub(crate) fn finish_frame(&mut self) {
+        self.frame_counter = self.frame_counter.wrapping_add(1);
+
+        self.advance_palette_animations();
     }
 
     pub fn render_scanline(&mut self, object_list: &[OamIndex], yi: u8) {
         //~ let object_list = self.get_objects_on_scanline(yi);
 
-        for xi in 0..SCREEN_WIDTH as u8 {
-            let pixel_index = Vfc::get_fb_pixel_index(xi, yi);
+        // only picking needs the registers saved, rendering reads the layers themselves
+        let bg_registers = match self.pick_buffer {
+            Some(_) => RasterRegisters::save_bg_layers(self),
+            None => vec![],
+        };
+
+        for xi in 0..self.config.screen_width {
+            let xi = xi as u8;
+            let pixel_index = self.get_fb_pixel_index(xi, yi);
 
             let LayerHit {
                 hit: palette_index,
-                layer: _layer,
+                layer,
                 priority: _priority,
             } = self.get_top_pixel(&object_list[..], xi, yi);
 
-            self.framebuffer[pixel_index] = self.palette[palette_index];
+            let rgb = self.get_layer_rgb(&self.palette, &layer, palette_index);
+
+            let rgb = match self.get_layer_blend(&layer) {
+                None => rgb,
+                Some(blend) => {
+                    let below = self.get_top_pixel_excluding(object_list, xi, yi, Some(&layer));
+
+                    blend.blend(
+                        rgb,
+                        self.get_layer_rgb(&self.palette, &below.layer, below.hit),
+                    )
+                }
+            };
+
+            self.framebuffer[pixel_index] = self.fade.apply(rgb);
+
+            // NOTE: blended pixels only record the top layer's palette index
+            if let Some(indexed_framebuffer) = &mut self.indexed_framebuffer {
+                indexed_framebuffer[pixel_index] = palette_index;
+            }
+
+            if self.pick_buffer.is_some() {
+                let pick = self.get_pick(&layer, &bg_registers, xi, yi);
+
+                if let Some(pick_buffer) = &mut self.pick_buffer {
+                    pick_buffer[pixel_index] = pick;
+                }
+            }
+        }
+    }
+
+    pub fn enable_indexed_framebuffer(&mut self) {
+        if self.indexed_framebuffer.is_none() {
+            self.indexed_framebuffer = Some(vec![
+                PaletteIndex::default();
+                self.config.num_screen_pixels()
+            ]);
+        }
+    }
+
+    pub fn disable_indexed_framebuffer(&mut self) {
+        self.indexed_framebuffer = None;
+    }
+
+    // redo the palette lookup of the last frame with a different palette, without re-rendering.
+    // the master fade is applied again, but per-layer fades and blending are lost.
+    // does nothing if the indexed framebuffer is not enabled
+    pub fn recolorize(&mut self, palette: &Palette) {
+        if let Some(indexed_framebuffer) = &self.indexed_framebuffer {
+            for (rgb, palette_index) in self.framebuffer.iter_mut().zip(indexed_framebuffer.iter())
+            {
+                *rgb = self.fade.apply(palette[*palette_index]);
+            }
         }
     }
 
@@ -409,13 +843,65 @@ impl Vfc {
         }
     }*/
 
-    pub fn get_fb_pixel_index(x: u8, y: u8) -> usize {
+    pub fn get_fb_pixel_index(&self, x: u8, y: u8) -> usize {
         let x = x as usize;
         let y = y as usize;
-        x + SCREEN_WIDTH * y
+        x + self.config.screen_width * y
     }
 
-    fn bg_layer_hit(&self, screen_pixel_x: u8, screen_pixel_y: u8) -> Option<LayerHit> {
+    // the raw pixel of one layer, ignoring the layers above it,
+    // or None if the layer isn't shown there
+    fn bg_layer_pixel(
+        &self,
+        layer: &BgLayer,
+        screen_pixel_x: u8,
+        screen_pixel_y: u8,
+    ) -> Option<(RawPixel, Subpalette)> {
+        if layer.hidden || !self.window_allows(&layer.window, screen_pixel_x, screen_pixel_y) {
+            return None;
+        }
+
+        let (relative_x, relative_y) = layer.map_pixel_at(
+            (layer.x, layer.y),
+            layer.affine.as_ref(),
+            screen_pixel_x,
+            screen_pixel_y,
+        )?;
+
+        let tile_x = relative_x / TILE_SIZE as u8;
+        let tile_y = relative_y / TILE_SIZE as u8;
+
+        let tile_pixel_x = relative_x % TILE_SIZE as u8;
+        let tile_pixel_y = relative_y % TILE_SIZE as u8;
+
+        let tile_index = layer.get_tile_index(tile_x, tile_y);
+
+        // the layer's tile bank picks which 256 tiles of the tileset it can use
+        //~ let tile = self.tileset.get_tile(tile_index);
+
+        //~ let pixel = tile.get_pixel(tile_pixel_x, tile_pixel_y);
+
+        let attributes = layer.get_tile_attribute(tile_x, tile_y);
+
+        // same flip and rotation handling as sprites
+        let pixel = self.get_tile_pixel_rotated(
+            layer.tile_bank,
+            tile_index,
+            attributes,
+            SpriteSize::Size8x8,
+            tile_pixel_x,
+            tile_pixel_y,
+        );
+
+        Some((pixel, attributes.get_palette()))
+    }
+
+    fn bg_layer_hit(
+        &self,
+        screen_pixel_x: u8,
+        screen_pixel_y: u8,
+        exclude: Option<&LayerType>,
+    ) -> Option<LayerHit> {
         let screen_pixel_x = screen_pixel_x as u8;
         let screen_pixel_y = screen_pixel_y as u8;
 
@@ -427,31 +913,15 @@ impl Vfc {
             //~ let priority = layer_index + 1;
             let priority = layer_index;
 
-            if layer.hidden {
+            if exclude == Some(&LayerType::BgLayer(layer_index as u8)) {
                 continue;
             }
 
-            let relative_x = screen_pixel_x.wrapping_sub(layer.x);
-            let relative_y = screen_pixel_y.wrapping_sub(layer.y);
-
-            let tile_x = relative_x / TILE_SIZE as u8;
-            let tile_y = relative_y / TILE_SIZE as u8;
-
-            let tile_pixel_x = relative_x % TILE_SIZE as u8;
-            let tile_pixel_y = relative_y % TILE_SIZE as u8;
-
-            let tile_index = layer.get_tile_index(tile_x, tile_y);
-
-            // TODO: there's no offset that allows backgrounds
-            // to use tile data past the first 256 tiles.
-            // do we want this?
-            //~ let tile = self.tileset.get_tile(tile_index);
-
-            //~ let pixel = tile.get_pixel(tile_pixel_x, tile_pixel_y);
-
-            let pixel = self.get_tile_pixel(tile_index, tile_pixel_x, tile_pixel_y);
-
-            let subpalette = layer.get_tile_attribute(tile_x, tile_y).get_palette();
+            let Some((pixel, subpalette)) =
+                self.bg_layer_pixel(layer, screen_pixel_x, screen_pixel_y)
+            else {
+                continue;
+            };
 
             let colorized_pixel = subpalette.colorize_pixel(pixel);
 
@@ -488,54 +958,68 @@ impl Vfc {
         })
     }
 
-    fn _get_objects_on_scanline_buffered(
-        &self,
-        output_list: &mut [Option<OamIndex>; OBJECTS_PER_LINE],
-        scanline: u8,
-    ) {
-        let mut list_index = 0;
+    fn get_objects_on_scanline(&self, scanline: u8) -> Vec<OamIndex> {
+        let mut sorted_objects = Vec::with_capacity(OBJECTS_PER_LINE);
 
-        for object_index in 0..NUM_OAM_ENTRIES {
+        for object_index in 0..self.oam.0.len() {
             let object = &self.oam[OamIndex(object_index as u8)];
 
-            if scanline >= object.y && scanline < object.y.wrapping_add(TILE_HEIGHT as u8) {
-                output_list[list_index] = Some(OamIndex(object_index as u8));
-
-                list_index += 1;
-
-                if list_index >= OBJECTS_PER_LINE {
-                    break;
-                }
+            //~ if scanline >= object.y && scanline < bottom {
+            if scanline.wrapping_sub(object.y) < object.height() {
+                sorted_objects.push(OamIndex(object_index as u8));
             }
         }
-    }
 
-    fn get_objects_on_scanline(&self, scanline: u8) -> Vec<OamIndex> {
-        let mut sorted_objects = Vec::with_capacity(OBJECTS_PER_LINE);
+        sorted_objects
+    }
 
-        for object_index in 0..NUM_OAM_ENTRIES {
-            let object = &self.oam[OamIndex(object_index as u8)];
+    fn limit_objects_on_scanline(
+        &mut self,
+        mut object_list: Vec<OamIndex>,
+        scanline: u8,
+    ) -> Vec<OamIndex> {
+        if object_list.len() <= OBJECTS_PER_LINE {
+            return object_list;
+        }
 
-            let bottom = object.y.wrapping_add(TILE_HEIGHT as u8);
+        self.object_overflow.lines[scanline as usize] = true;
 
-            //~ if scanline >= object.y && scanline < bottom {
-            if (scanline >= object.y || object.y > 0u8.wrapping_sub(TILE_HEIGHT as u8))
-                && scanline < bottom
-            {
-                sorted_objects.push(OamIndex(object_index as u8));
+        match self.object_limit {
+            ObjectLimit::Unlimited => (),
+            ObjectLimit::Drop => object_list.truncate(OBJECTS_PER_LINE),
+            ObjectLimit::Flicker => {
+                let start = self.frame_counter.wrapping_mul(OBJECTS_PER_LINE) % object_list.len();
+                object_list.rotate_left(start);
+                object_list.truncate(OBJECTS_PER_LINE);
             }
         }
 
-        sorted_objects
+        object_list
     }
 
     fn get_top_pixel(&self, object_list: &[OamIndex], pixel_x: u8, pixel_y: u8) -> LayerHit {
+        self.get_top_pixel_excluding(object_list, pixel_x, pixel_y, None)
+    }
+
+    // the top pixel as if the `exclude` layer or sprite wasn't there,
+    // which is the pixel a translucent layer blends with
+    fn get_top_pixel_excluding(
+        &self,
+        object_list: &[OamIndex],
+        pixel_x: u8,
+        pixel_y: u8,
+        exclude: Option<&LayerType>,
+    ) -> LayerHit {
         let oam_hit = 'l: {
-            if self.oam_hidden {
+            if self.oam_hidden || !self.window_allows(&self.oam_window, pixel_x, pixel_y) {
                 break 'l None;
             }
 
             for index in object_list.iter() {
+                if exclude == Some(&LayerType::Oam(*index)) {
+                    continue;
+                }
+
                 let oam_entry = &self.oam[*index];
 
                 // TODO: we could have an early out here but the math below doesn't work for sprites off the top or left edge
@@ -572,11 +1056,11 @@ impl Vfc {
             None
         };
 
-        let bg_hit = self.bg_layer_hit(pixel_x, pixel_y);
+        let bg_hit = self.bg_layer_hit(pixel_x, pixel_y, exclude);
 
         let hit = 'l: {
             for priority in (0..(NUM_OBJECT_PRIORITY_LEVELS as u8)).rev() {
-            //~ for priority in 0..(NUM_OBJECT_PRIORITY_LEVELS as u8) {
+                //~ for priority in 0..(NUM_OBJECT_PRIORITY_LEVELS as u8) {
                 let oam_priority = oam_hit.as_ref().map(|hit| hit.priority);
                 let bg_priority = bg_hit.as_ref().map(|hit| hit.priority);
                 match (oam_priority, bg_priority) {
@@ -603,6 +1087,35 @@ impl Vfc {
         })
     }
 
+    // palette lookup, plus the layer's own fade
+    fn get_layer_rgb(
+        &self,
+        palette: &Palette,
+        layer: &LayerType,
+        palette_index: PaletteIndex,
+    ) -> Rgb {
+        let rgb = palette[palette_index];
+
+        let fade = match layer {
+            LayerType::BgColor => None,
+            LayerType::BgLayer(layer_index) => self.bg_layers[*layer_index as usize].fade,
+            LayerType::Oam(_) => self.oam_fade,
+        };
+
+        match fade {
+            Some(fade) => fade.apply(rgb),
+            None => rgb,
+        }
+    }
+
+    fn get_layer_blend(&self, layer: &LayerType) -> Option<BlendMode> {
+        match layer {
+            LayerType::BgColor => None,
+            LayerType::BgLayer(layer_index) => self.bg_layers[*layer_index as usize].blend,
+            LayerType::Oam(oam_index) => self.oam[*oam_index].blend,
+        }
+    }
+
     // get a pixel in screen coords
     pub fn get_tile_pixel_global(
         &self,
@@ -617,109 +1130,120 @@ impl Vfc {
         let local_x = screen_x.wrapping_sub(oam_entry.x);
         let local_y = screen_y.wrapping_sub(oam_entry.y);
 
-        self.get_tile_pixel_rotated(tile_index, &oam_entry.attributes, local_x, local_y)
+        self.get_tile_pixel_rotated(
+            oam_entry.tile_bank,
+            tile_index,
+            &oam_entry.attributes,
+            oam_entry.size,
+            local_x,
+            local_y,
+        )
     }
 
     // NOTE: not tested with tiles wider than 8 pixels
-    fn get_tile_pixel(&self, tile_index: TileIndex, pixel_x: u8, pixel_y: u8) -> RawPixel {
+    fn get_tile_pixel(
+        &self,
+        bank: TileBank,
+        tile_index: TileIndex,
+        pixel_x: u8,
+        pixel_y: u8,
+    ) -> RawPixel {
         //~ let pixel_x = pixel_x % TILE_WIDTH as u8;
         //~ let pixel_y = pixel_y % TILE_HEIGHT as u8;
 
-        let pixel = (0..NUM_PLANES).fold(0, |acc, plane_index| {
-            (acc << 1)
-                | ((self.tileset.pixel_data[plane_index][tile_index.0 as usize][pixel_y as usize]
-                    as usize
-                    >> pixel_x)
-                    & 1)
-        });
-
-        RawPixel(pixel as u8)
+        self.tileset.get_pixel(bank, tile_index, pixel_x, pixel_y)
     }
 
     // NOTE: not tested with tiles wider than 8 pixels
     fn get_tile_pixel_rotated(
         &self,
+        bank: TileBank,
         tile_index: TileIndex,
         attributes: &TileAttributes,
+        size: SpriteSize,
         pixel_x: u8,
         pixel_y: u8,
     ) -> RawPixel {
-        if pixel_x != pixel_x % TILE_WIDTH as u8 || pixel_y != pixel_y % TILE_HEIGHT as u8 {
-            return RawPixel(0);
+        match rotated_tile_pixel(tile_index, attributes, size, pixel_x, pixel_y) {
+            Some((tile_index, pixel_x, pixel_y)) => {
+                self.get_tile_pixel(bank, tile_index, pixel_x, pixel_y)
+            }
+            None => RawPixel(0),
         }
-        //~ let pixel_x = pixel_x % TILE_WIDTH as u8;
-        //~ let pixel_y = pixel_y % TILE_HEIGHT as u8;
-
-        let rotation = attributes.get_rotation();
-
-        let flip_x = rotation & 1 != 0;
-        let flip_y = (rotation >> 1) & 1 != 0;
-        let flip_diagonal = (rotation >> 2) & 1 != 0;
-        //~ let flip_x = true;
-        //~ let flip_y = false;
-        //~ let flip_diagonal = true;
-
-        let (pixel_x, pixel_y) = (pixel_x as usize, pixel_y as usize);
-
-        let pixel_x = if flip_x {
-            TILE_WIDTH - 1 - pixel_x
-        } else {
-            pixel_x
-        };
-
-        let pixel_y = if flip_y {
-            TILE_HEIGHT - 1 - pixel_y
-        } else {
-            pixel_y
-        };
-
-        let (pixel_x, pixel_y) = if flip_diagonal {
-            (pixel_y, pixel_x)
-        } else {
-            (pixel_x, pixel_y)
-        };
-
-        let pixel = (0..NUM_PLANES).fold(0, |acc, plane_index| {
-            (acc << 1)
-                | ((self.tileset.pixel_data[plane_index][tile_index.0 as usize][pixel_y as usize]
-                    as usize
-                    >> pixel_x)
-                    & 1)
-        });
+    }
+}
 
-        RawPixel(pixel as u8)
+// which 8x8 tile of a (possibly large, flipped) sprite or bg tile a pixel comes from,
+// and where in that tile. None outside of the footprint
+pub(crate) fn rotated_tile_pixel(
+    tile_index: TileIndex,
+    attributes: &TileAttributes,
+    size: SpriteSize,
+    pixel_x: u8,
+    pixel_y: u8,
+) -> Option<(TileIndex, u8, u8)> {
+    let rotation = attributes.get_rotation();
+
+    let flip_x = rotation & 1 != 0;
+    let flip_y = (rotation >> 1) & 1 != 0;
+    let flip_diagonal = (rotation >> 2) & 1 != 0;
+    //~ let flip_x = true;
+    //~ let flip_y = false;
+    //~ let flip_diagonal = true;
+
+    // a diagonal flip turns a tall sprite into a wide one
+    let (width, height) = if flip_diagonal {
+        (size.height(), size.width())
+    } else {
+        (size.width(), size.height())
+    };
+
+    let (pixel_x, pixel_y) = (pixel_x as usize, pixel_y as usize);
+
+    if pixel_x >= width || pixel_y >= height {
+        return None;
     }
+
+    let pixel_x = if flip_x { width - 1 - pixel_x } else { pixel_x };
+
+    let pixel_y = if flip_y {
+        height - 1 - pixel_y
+    } else {
+        pixel_y
+    };
+
+    let (pixel_x, pixel_y) = if flip_diagonal {
+        (pixel_y, pixel_x)
+    } else {
+        (pixel_x, pixel_y)
+    };
+
+    // the tiles of a large sprite are sequential, left to right then top to bottom
+    let tile_offset = pixel_x / TILE_WIDTH + pixel_y / TILE_HEIGHT * size.tiles_wide();
+    let tile_index = TileIndex(tile_index.0.wrapping_add(tile_offset as u8));
+
+    Some((
+        tile_index,
+        (pixel_x % TILE_WIDTH) as u8,
+        (pixel_y % TILE_HEIGHT) as u8,
+    ))
 }
 
 impl Default for Palette {
     fn default() -> Self {
-        Self([(); NUM_PALETTE_ENTRIES].map(|_| Rgb::default()))
+        Self::with_size(NUM_PALETTE_ENTRIES)
     }
 }
 
 impl Default for BgLayer {
     fn default() -> Self {
-        Self {
-            tiles: [(); NUM_BG_TILES].map(|_| TileIndex::default()),
-            attributes: [(); NUM_BG_TILES].map(|_| TileAttributes::default()),
-            x: 0,
-            y: 0,
-            hidden: false,
-        }
+        Self::new(BG_WIDTH, BG_HEIGHT)
     }
 }
 
 impl Default for Vfc {
     fn default() -> Self {
-        Self {
-            oam: OamTable::default(),
-            oam_hidden: false,
-            framebuffer: [(); NUM_SCREEN_PIXELS].map(|_| Rgb::default()),
-            palette: Palette::default(),
-            background_color: PaletteIndex::default(),
-            tileset: Tileset::new(),
-            bg_layers: Default::default(),
-        }
+        Self::build(VfcConfig::default())
     }
 }
 
@@ -729,6 +1253,634 @@ impl Default for Tileset {
             [(); NUM_TILES].map(|_| [(); BYTES_PER_TILE_PLANE].map(|_| Default::default()))
         });
 
-        Tileset { pixel_data }
+        Tileset {
+            pixel_data,
+            generation: next_tileset_generation(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testing::GLYPH;
+
+    fn gradient_palette(offset: u8) -> Palette {
+        Palette::new(
+            (0..NUM_PALETTE_ENTRIES)
+                .map(|i| Rgb::new(i as u8 * 4, offset, 0))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn indexed_framebuffer_recolorize() {
+        let mut fc = Vfc::new();
+        fc.palette = gradient_palette(0);
+        fc.background_color = PaletteIndex(3);
+        fc.tileset.write_tile(
+            TileIndex(1),
+            testing::planar_tile(&[[0, 1, 2, 3, 4, 5, 6, 7]; 8]),
+        );
+        fc.oam.0[0] = OamEntry::new(8, 8, TileIndex(1), TileAttributes::default());
+
+        fc.render_frame();
+        assert!(fc.indexed_framebuffer.is_none());
+
+        fc.enable_indexed_framebuffer();
+        fc.render_frame();
+
+        let indexed = fc.indexed_framebuffer.as_ref().unwrap();
+        assert_eq!(indexed[fc.get_fb_pixel_index(0, 0)], PaletteIndex(3));
+        assert_eq!(indexed[fc.get_fb_pixel_index(10, 8)], PaletteIndex(2));
+        assert_eq!(indexed[fc.get_fb_pixel_index(8, 8)], PaletteIndex(3));
+
+        let original = fc.framebuffer.clone();
+        let faded = gradient_palette(0x80);
+
+        fc.recolorize(&faded);
+        for (i, rgb) in fc.framebuffer.iter().enumerate() {
+            let palette_index = fc.indexed_framebuffer.as_ref().unwrap()[i];
+            assert_eq!(*rgb, faded[palette_index]);
+        }
+
+        fc.recolorize(&gradient_palette(0));
+        assert!(fc.framebuffer == original);
+    }
+
+    #[test]
+    fn raster_table_split_screen() {
+        let mut fc = Vfc::new();
+        fc.palette = gradient_palette(0);
+        fc.tileset.write_tile(
+            TileIndex(1),
+            testing::planar_tile(&[[1, 2, 3, 4, 5, 6, 7, 7]; 8]),
+        );
+        fc.bg_layers[0].tiles[0] = TileIndex(1);
+
+        // the top 8 lines show the tile unscrolled, everything below is scrolled 2 pixels left
+        fc.bg_layers[0].x = 0;
+        fc.raster_table
+            .push(8, RegisterWrite::BgX(0, 0u8.wrapping_sub(2)));
+        fc.raster_table.push(8, RegisterWrite::BgY(0, 8));
+        fc.raster_table
+            .push(12, RegisterWrite::BackgroundColor(PaletteIndex(9)));
+        fc.raster_table.push(
+            12,
+            RegisterWrite::PaletteEntry(PaletteIndex(3), Rgb::new(1, 2, 3)),
+        );
+
+        fc.enable_indexed_framebuffer();
+        fc.render_frame();
+
+        let indexed = fc.indexed_framebuffer.as_ref().unwrap();
+        assert_eq!(indexed[fc.get_fb_pixel_index(0, 0)], PaletteIndex(1));
+        assert_eq!(indexed[fc.get_fb_pixel_index(0, 8)], PaletteIndex(3));
+        assert_eq!(indexed[fc.get_fb_pixel_index(0, 12)], PaletteIndex(3));
+        assert_eq!(indexed[fc.get_fb_pixel_index(100, 12)], PaletteIndex(9));
+        assert_eq!(
+            fc.framebuffer[fc.get_fb_pixel_index(0, 8)],
+            Rgb::new(12, 0, 0)
+        );
+        assert_eq!(
+            fc.framebuffer[fc.get_fb_pixel_index(0, 13)],
+            Rgb::new(1, 2, 3)
+        );
+
+        // registers are back to how the game left them
+        assert_eq!(fc.bg_layers[0].x, 0);
+        assert_eq!(fc.bg_layers[0].y, 0);
+        assert_eq!(fc.background_color, PaletteIndex(0));
+        assert_eq!(fc.palette[PaletteIndex(3)], Rgb::new(12, 0, 0));
+    }
+
+    #[test]
+    fn hblank_callback() {
+        let mut fc = Vfc::new();
+        fc.enable_indexed_framebuffer();
+
+        let mut lines = vec![];
+        fc.render_frame_with_hblank(|fc, scanline| {
+            fc.background_color = PaletteIndex(scanline % 8);
+            lines.push(scanline);
+        });
+
+        assert_eq!(lines, (0..SCREEN_HEIGHT as u8).collect::<Vec<_>>());
+
+        let indexed = fc.indexed_framebuffer.as_ref().unwrap();
+        for y in 0..SCREEN_HEIGHT as u8 {
+            assert_eq!(indexed[fc.get_fb_pixel_index(7, y)], PaletteIndex(y % 8));
+        }
+
+        // what the callback wrote stays, with or without a raster table. raster writes don't
+        let last_line = PaletteIndex((SCREEN_HEIGHT as u8 - 1) % 8);
+        assert_eq!(fc.background_color, last_line);
+
+        fc.background_color = PaletteIndex(0);
+        fc.raster_table.push(10, RegisterWrite::BgX(0, 5));
+        fc.render_frame_with_hblank(|fc, scanline| {
+            fc.background_color = PaletteIndex(scanline % 8);
+            if scanline == 20 {
+                fc.bg_layers[1].y = 7;
+            }
+        });
+        assert_eq!(fc.background_color, last_line);
+        assert_eq!(fc.bg_layers[1].y, 7);
+        assert_eq!(fc.bg_layers[0].x, 0);
+
+        // a register both write goes back to how it was before the frame, even though the
+        // callback wrote it after the raster table did
+        fc.render_frame_with_hblank(|fc, scanline| {
+            if scanline == 20 {
+                fc.bg_layers[0].x = 9;
+            }
+        });
+        assert_eq!(fc.bg_layers[0].x, 0);
+    }
+
+    // 20 single-pixel sprites side by side on lines 16..24, each colored by its OAM index
+    fn crowded_line() -> Vfc {
+        let mut fc = Vfc::new();
+        fc.enable_indexed_framebuffer();
+        let mut dot = [[0; 8]; 8];
+        dot[0][0] = 1;
+        fc.tileset
+            .write_tile(TileIndex(1), testing::planar_tile(&dot));
+
+        for i in 0..20 {
+            fc.oam.0[i] = OamEntry::new(
+                i as u8 * 8,
+                16,
+                TileIndex(1),
+                TileAttributes::default().with_palette(Subpalette::new(i as u8 % 8)),
+            );
+        }
+
+        fc
+    }
+
+    fn visible_objects(fc: &Vfc) -> Vec<usize> {
+        let indexed = fc.indexed_framebuffer.as_ref().unwrap();
+        (0..20)
+            .filter(|i| indexed[fc.get_fb_pixel_index(*i as u8 * 8, 16)] != PaletteIndex(0))
+            .collect()
+    }
+
+    #[test]
+    fn object_limit_drop() {
+        let mut fc = crowded_line();
+
+        fc.render_frame();
+        assert_eq!(visible_objects(&fc).len(), 20);
+        assert!(fc.object_overflow.any());
+
+        fc.object_limit = ObjectLimit::Drop;
+        fc.render_frame();
+        assert_eq!(
+            visible_objects(&fc),
+            (0..OBJECTS_PER_LINE).collect::<Vec<_>>()
+        );
+        assert_eq!(fc.object_overflow.first_line(), Some(16));
+        assert!(fc.object_overflow.lines[23]);
+        assert!(!fc.object_overflow.lines[24]);
+
+        fc.oam.0[19].hide();
+        fc.oam.0[18].hide();
+        fc.oam.0[17].hide();
+        fc.oam.0[16].hide();
+        fc.render_frame();
+        assert_eq!(visible_objects(&fc).len(), 16);
+        assert!(!fc.object_overflow.any());
+    }
+
+    #[test]
+    fn object_limit_flicker() {
+        let mut fc = crowded_line();
+        fc.object_limit = ObjectLimit::Flicker;
+
+        let mut seen = [false; 20];
+        for _ in 0..4 {
+            fc.render_frame();
+            let visible = visible_objects(&fc);
+            assert_eq!(visible.len(), OBJECTS_PER_LINE);
+            for i in visible {
+                seen[i] = true;
+            }
+        }
+
+        assert!(seen.iter().all(|&seen| seen));
+    }
+
+    // tiles 1.. are solid, each in its own color, so the tile order shows up on screen
+    fn solid_tiles() -> Vfc {
+        let mut fc = Vfc::new();
+        fc.enable_indexed_framebuffer();
+        for i in 1..8 {
+            fc.tileset
+                .write_tile(TileIndex(i), testing::planar_tile(&[[i; 8]; 8]));
+        }
+        fc
+    }
+
+    // the palette index in the middle of each 8x8 cell of a sprite's footprint
+    fn cells(fc: &Vfc, x: u8, y: u8, columns: u8, rows: u8) -> Vec<Vec<u8>> {
+        let indexed = fc.indexed_framebuffer.as_ref().unwrap();
+        (0..rows)
+            .map(|row| {
+                (0..columns)
+                    .map(|column| {
+                        indexed[fc.get_fb_pixel_index(x + column * 8 + 3, y + row * 8 + 3)].0
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn large_sprites() {
+        let mut fc = solid_tiles();
+        fc.oam.0[0] = OamEntry::new(8, 8, TileIndex(1), TileAttributes::default())
+            .with_size(SpriteSize::Size16x16);
+        fc.oam.0[1] = OamEntry::new(
+            40,
+            8,
+            TileIndex(1),
+            TileAttributes::default().with_rotation(1),
+        )
+        .with_size(SpriteSize::Size16x16);
+        fc.oam.0[2] = OamEntry::new(
+            72,
+            8,
+            TileIndex(1),
+            TileAttributes::default().with_rotation(2),
+        )
+        .with_size(SpriteSize::Size8x16);
+        fc.oam.0[3] = OamEntry::new(
+            104,
+            8,
+            TileIndex(1),
+            TileAttributes::default().with_rotation(4),
+        )
+        .with_size(SpriteSize::Size8x16);
+
+        fc.render_frame();
+
+        assert_eq!(cells(&fc, 8, 8, 3, 3), [[1, 2, 0], [3, 4, 0], [0, 0, 0]]);
+        assert_eq!(cells(&fc, 40, 8, 3, 3), [[2, 1, 0], [4, 3, 0], [0, 0, 0]]);
+        assert_eq!(cells(&fc, 72, 8, 2, 3), [[2, 0], [1, 0], [0, 0]]);
+        // flipped diagonally, a tall sprite lies on its side
+        assert_eq!(cells(&fc, 104, 8, 3, 2), [[1, 2, 0], [0, 0, 0]]);
+    }
+
+    #[test]
+    fn large_sprites_off_the_edge() {
+        let mut fc = solid_tiles();
+        fc.oam.0[0] = OamEntry::new(
+            0u8.wrapping_sub(8),
+            0u8.wrapping_sub(24),
+            TileIndex(0),
+            TileAttributes::default(),
+        )
+        .with_size(SpriteSize::Size16x32);
+
+        assert!(fc.oam.0[0].bounding_box_contains_pixel(7, 7));
+        assert!(!fc.oam.0[0].bounding_box_contains_pixel(8, 7));
+        assert!(!fc.oam.0[0].bounding_box_contains_pixel(7, 8));
+
+        fc.render_frame();
+
+        // only the bottom right tile of the 2x4 block is on screen
+        assert_eq!(cells(&fc, 0, 0, 2, 2), [[7, 0], [0, 0]]);
+    }
+
+    #[test]
+    fn tile_banks() {
+        let mut fc = Vfc::new();
+        fc.enable_indexed_framebuffer();
+        for bank in 0..NUM_TILE_BANKS as u8 {
+            fc.tileset.write_tile_in_bank(
+                TileBank(bank),
+                TileIndex(5),
+                testing::planar_tile(&[[bank + 1; 8]; 8]),
+            );
+        }
+
+        fc.bg_layers[0].tiles[0] = TileIndex(5);
+        fc.bg_layers[0].tiles[1] = TileIndex(5);
+        fc.bg_layers[0].tile_bank = TileBank(1);
+        fc.oam.0[0] = OamEntry::new(8, 0, TileIndex(5), TileAttributes::default())
+            .with_tile_bank(TileBank(NUM_TILE_BANKS as u8 - 1));
+
+        fc.render_frame();
+
+        assert_eq!(cells(&fc, 0, 0, 3, 1), [[2, NUM_TILE_BANKS as u8, 0]]);
+    }
+
+    #[test]
+    fn bg_tile_orientations() {
+        let mut fc = Vfc::new();
+        fc.enable_indexed_framebuffer();
+        fc.tileset
+            .write_tile(TileIndex(1), testing::planar_tile(&GLYPH));
+
+        // one background tile and one sprite per orientation, the sprites a row below
+        for rotation in 0..8 {
+            fc.bg_layers[0].tiles[rotation as usize] = TileIndex(1);
+            fc.bg_layers[0].attributes[rotation as usize].set_rotation(rotation);
+            fc.oam.0[rotation as usize] = OamEntry::new(
+                rotation * 8,
+                8,
+                TileIndex(1),
+                TileAttributes::default().with_rotation(rotation),
+            );
+        }
+
+        fc.render_frame();
+
+        let indexed = fc.indexed_framebuffer.as_ref().unwrap();
+        let bg_pixel = |rotation: u8, x: usize, y: usize| {
+            indexed[fc.get_fb_pixel_index(rotation * 8 + x as u8, y as u8)].0
+        };
+        let oam_pixel = |rotation: u8, x: usize, y: usize| {
+            indexed[fc.get_fb_pixel_index(rotation * 8 + x as u8, 8 + y as u8)].0
+        };
+
+        for y in 0..TILE_HEIGHT {
+            for x in 0..TILE_WIDTH {
+                // unrotated
+                assert_eq!(bg_pixel(0, x, y), GLYPH[y][x]);
+                // flip x
+                assert_eq!(bg_pixel(1, x, y), GLYPH[y][7 - x]);
+                // flip y
+                assert_eq!(bg_pixel(2, x, y), GLYPH[7 - y][x]);
+                // 180 degrees
+                assert_eq!(bg_pixel(3, x, y), GLYPH[7 - y][7 - x]);
+                // transposed
+                assert_eq!(bg_pixel(4, x, y), GLYPH[x][y]);
+                // 90 degrees clockwise
+                assert_eq!(bg_pixel(5, x, y), GLYPH[7 - x][y]);
+                // 90 degrees counterclockwise
+                assert_eq!(bg_pixel(6, x, y), GLYPH[x][7 - y]);
+                // anti-transposed
+                assert_eq!(bg_pixel(7, x, y), GLYPH[7 - x][7 - y]);
+
+                for rotation in 0..8 {
+                    assert_eq!(bg_pixel(rotation, x, y), oam_pixel(rotation, x, y));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn affine_bg() {
+        let mut fc = Vfc::new();
+        fc.enable_indexed_framebuffer();
+        fc.tileset
+            .write_tile(TileIndex(1), testing::planar_tile(&GLYPH));
+        for i in 0..NUM_BG_TILES {
+            fc.bg_layers[0].tiles[i] = TileIndex(1);
+            fc.bg_layers[0].attributes[i].set_palette(Subpalette::new((i % 7) as u8));
+        }
+
+        fc.render_frame();
+        let plain = fc.indexed_framebuffer.clone().unwrap();
+
+        fc.bg_layers[0].x = 33;
+        fc.bg_layers[0].affine = Some(Affine::identity());
+        fc.render_frame();
+        assert!(fc.indexed_framebuffer.as_ref().unwrap()[..] == plain[..]);
+
+        // half a turn around the middle of the screen
+        fc.bg_layers[0].affine = Some(Affine::rotate_scale(
+            std::f64::consts::PI,
+            1.0,
+            (96, 80),
+            (96, 80),
+        ));
+        fc.render_frame();
+        let indexed = fc.indexed_framebuffer.as_ref().unwrap();
+        for y in 1..SCREEN_HEIGHT as u8 {
+            for x in 1..SCREEN_WIDTH as u8 {
+                assert_eq!(
+                    indexed[fc.get_fb_pixel_index(x, y)],
+                    plain[fc.get_fb_pixel_index(192 - x, 160 - y)]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn affine_bg_per_scanline() {
+        let mut fc = Vfc::new();
+        fc.enable_indexed_framebuffer();
+        fc.tileset.write_tile(
+            TileIndex(1),
+            testing::planar_tile(&[[1, 2, 3, 4, 5, 6, 7, 1]; 8]),
+        );
+        for i in 0..NUM_BG_TILES {
+            fc.bg_layers[0].tiles[i] = TileIndex(1);
+        }
+
+        // zoom out further down the screen, like a floor stretching into the distance
+        for scanline in 0..SCREEN_HEIGHT as u8 {
+            let scale = AFFINE_ONE * (1 + scanline as i32 / 40);
+            let affine = Affine {
+                a: scale,
+                ..Affine::identity()
+            };
+            fc.raster_table
+                .push(scanline, RegisterWrite::BgAffine(0, Some(affine)));
+        }
+
+        fc.render_frame();
+
+        let indexed = fc.indexed_framebuffer.as_ref().unwrap();
+        assert_eq!(indexed[fc.get_fb_pixel_index(1, 0)].0, 2);
+        assert_eq!(indexed[fc.get_fb_pixel_index(1, 40)].0, 3);
+        assert_eq!(indexed[fc.get_fb_pixel_index(1, 80)].0, 4);
+        assert_eq!(indexed[fc.get_fb_pixel_index(1, 120)].0, 5);
+        assert!(fc.bg_layers[0].affine.is_none());
+    }
+
+    #[test]
+    fn windows() {
+        let mut fc = solid_tiles();
+        for i in 0..NUM_BG_TILES {
+            fc.bg_layers[0].tiles[i] = TileIndex(1);
+            fc.bg_layers[1].tiles[i] = TileIndex(2);
+        }
+        fc.oam.0[0] = OamEntry::new(140, 0, TileIndex(3), TileAttributes::default());
+
+        // a hud panel on the right that the playfield and sprites stay out of
+        fc.windows[0] = Window::new(144, 0, 191, 159);
+        fc.bg_layers[0].window = WindowMask::outside(0);
+        fc.bg_layers[1].window = WindowMask::inside(0);
+        fc.oam_window = WindowMask::outside(0);
+
+        fc.render_frame();
+
+        assert_eq!(cells(&fc, 128, 0, 3, 1), [[1, 1, 2]]);
+        let indexed = fc.indexed_framebuffer.as_ref().unwrap();
+        assert_eq!(indexed[fc.get_fb_pixel_index(143, 0)].0, 3);
+        assert_eq!(indexed[fc.get_fb_pixel_index(144, 0)].0, 2);
+        assert_eq!(indexed[fc.get_fb_pixel_index(191, 159)].0, 2);
+    }
+
+    #[test]
+    fn window_per_scanline() {
+        let mut fc = solid_tiles();
+        for i in 0..NUM_BG_TILES {
+            fc.bg_layers[0].tiles[i] = TileIndex(1);
+        }
+        fc.background_color = PaletteIndex(7);
+
+        // a diamond shaped spotlight, 20 pixels from the middle to each corner
+        fc.bg_layers[0].window = WindowMask::inside(1);
+        for y in 60..=100u8 {
+            let half_width = 20 - (y as i8 - 80).unsigned_abs();
+            fc.raster_table.push(
+                y,
+                RegisterWrite::Window(1, Window::new(96 - half_width, 0, 96 + half_width, 255)),
+            );
+        }
+        fc.raster_table
+            .push(101, RegisterWrite::Window(1, Window::empty()));
+
+        fc.render_frame();
+
+        let indexed = fc.indexed_framebuffer.as_ref().unwrap();
+        let pixel = |x, y| indexed[fc.get_fb_pixel_index(x, y)].0;
+        assert_eq!(pixel(96, 59), 7);
+        assert_eq!(pixel(96, 60), 1);
+        assert_eq!(pixel(76, 80), 1);
+        assert_eq!(pixel(75, 80), 7);
+        assert_eq!(pixel(116, 80), 1);
+        assert_eq!(pixel(117, 80), 7);
+        assert_eq!(pixel(96, 100), 1);
+        assert_eq!(pixel(96, 101), 7);
+        assert_eq!(fc.windows[1], Window::empty());
+    }
+
+    #[test]
+    fn color_math() {
+        let mut fc = solid_tiles();
+        fc.palette[PaletteIndex(0)] = Rgb::new(0x10, 0x10, 0x10);
+        fc.palette[PaletteIndex(1)] = Rgb::new(0x80, 0x40, 0x20);
+        fc.palette[PaletteIndex(2)] = Rgb::new(0x20, 0x60, 0xf0);
+        fc.palette[PaletteIndex(3)] = Rgb::new(0x30, 0x30, 0x30);
+
+        // bg1 is glass over the left half of bg0
+        for i in 0..NUM_BG_TILES {
+            fc.bg_layers[0].tiles[i] = TileIndex(if i % BG_WIDTH < 8 { 1 } else { 0 });
+            fc.bg_layers[1].tiles[i] = TileIndex(2);
+        }
+        fc.bg_layers[1].blend = Some(BlendMode::Average);
+
+        // a shadow on top of bg0, and another translucent sprite on top of that
+        fc.oam.0[0] = OamEntry::new(0, 8, TileIndex(3), TileAttributes::default())
+            .with_blend(BlendMode::Subtract);
+        fc.oam.0[1] = OamEntry::new(4, 12, TileIndex(2), TileAttributes::default())
+            .with_blend(BlendMode::Add);
+
+        fc.render_frame();
+
+        let pixel = |x, y| fc.framebuffer[fc.get_fb_pixel_index(x, y)];
+        // bg0 is in front of bg1, so bg1 only shows where bg0 is empty
+        assert_eq!(pixel(0, 0), Rgb::new(0x80, 0x40, 0x20));
+        assert_eq!(pixel(100, 0), Rgb::new(0x18, 0x38, 0x80));
+        assert_eq!(pixel(0, 8), Rgb::new(0x50, 0x10, 0x00));
+        // translucent sprites don't stack, the shadow mixes with the other sprite's own color
+        assert_eq!(pixel(4, 12), Rgb::new(0x00, 0x30, 0xc0));
+        assert_eq!(pixel(10, 16), Rgb::new(0xa0, 0xa0, 0xff));
+    }
+
+    #[test]
+    fn fades() {
+        let mut fc = solid_tiles();
+        fc.palette[PaletteIndex(0)] = Rgb::new(0x10, 0x20, 0x30);
+        fc.palette[PaletteIndex(1)] = Rgb::new(0x80, 0x40, 0x20);
+        fc.palette[PaletteIndex(2)] = Rgb::new(0x20, 0x60, 0xf0);
+        for i in 0..BG_WIDTH {
+            fc.bg_layers[0].tiles[i] = TileIndex(1);
+        }
+        fc.oam.0[0] = OamEntry::new(0, 16, TileIndex(2), TileAttributes::default());
+
+        // only sprites fade
+        fc.oam_fade = Some(Fade::to_white(255));
+        fc.render_frame();
+        let pixel = |fc: &Vfc, x, y| fc.framebuffer[fc.get_fb_pixel_index(x, y)];
+        assert_eq!(pixel(&fc, 0, 0), Rgb::new(0x80, 0x40, 0x20));
+        assert_eq!(pixel(&fc, 0, 16), Rgb::new(0xff, 0xff, 0xff));
+        assert_eq!(pixel(&fc, 8, 16), Rgb::new(0x10, 0x20, 0x30));
+
+        // the whole screen fades, on top of the per-layer fade
+        fc.fade = Fade::to_black(255);
+        fc.render_frame();
+        assert!(fc.framebuffer.iter().all(|&rgb| rgb == Rgb::new(0, 0, 0)));
+
+        // a gradient sky, getting darker further down, without touching the palette
+        fc.oam_fade = None;
+        fc.fade = Fade::none();
+        for scanline in 0..SCREEN_HEIGHT as u8 {
+            fc.raster_table
+                .push(scanline, RegisterWrite::Fade(Fade::to_black(scanline)));
+        }
+        fc.render_frame();
+        assert_eq!(pixel(&fc, 0, 0), Rgb::new(0x80, 0x40, 0x20));
+        assert_eq!(pixel(&fc, 8, 153), Rgb::new(0x07, 0x0d, 0x14));
+        assert_eq!(fc.fade, Fade::none());
+        assert_eq!(fc.palette[PaletteIndex(0)], Rgb::new(0x10, 0x20, 0x30));
+    }
+
+    #[test]
+    fn custom_config() {
+        assert_eq!(
+            Vfc::with_config(VfcConfig::new().with_bg_layers(8)).err(),
+            Some(ConfigError::BgLayers(8))
+        );
+
+        // a handheld-sized screen with a small map, a short oam and one subpalette
+        let config = VfcConfig::new()
+            .with_screen_size(160, 144)
+            .with_bg_layers(3)
+            .with_bg_size(16, 8)
+            .with_oam_entries(40)
+            .with_palette_entries(8);
+        let mut fc = Vfc::with_config(config).unwrap();
+        assert_eq!(fc.config(), &config);
+        assert_eq!(fc.framebuffer.len(), 160 * 144);
+        assert_eq!(fc.bg_layers.len(), 3);
+        assert_eq!(fc.bg_layers[2].tiles.len(), 16 * 8);
+        assert_eq!(fc.oam.0.len(), 40);
+        assert_eq!(fc.palette.len(), 8);
+
+        fc.enable_indexed_framebuffer();
+        fc.tileset
+            .write_tile(TileIndex(1), testing::planar_tile(&[[3; 8]; 8]));
+        fc.bg_layers[2].tiles[0] = TileIndex(1);
+        fc.oam.0[39] = OamEntry::new(152, 136, TileIndex(1), TileAttributes::default());
+        fc.render_frame();
+
+        // the 128x64 pixel map repeats across the screen
+        let indexed = fc.indexed_framebuffer.as_ref().unwrap();
+        assert_eq!(indexed[fc.get_fb_pixel_index(0, 0)], PaletteIndex(3));
+        assert_eq!(indexed[fc.get_fb_pixel_index(8, 0)], PaletteIndex(0));
+        assert_eq!(indexed[fc.get_fb_pixel_index(128, 64)], PaletteIndex(3));
+        assert_eq!(indexed[fc.get_fb_pixel_index(159, 143)], PaletteIndex(3));
+
+        // a full 256 pixel wide screen draws every column, and hidden sprites stay hidden
+        let mut fc = Vfc::with_config(VfcConfig::new().with_screen_size(256, 224)).unwrap();
+        fc.palette[PaletteIndex(1)] = Rgb::new(0, 0, 0xff);
+        fc.background_color = PaletteIndex(1);
+        fc.tileset
+            .write_tile(TileIndex(0), testing::planar_tile(&[[2; 8]; 8]));
+        for layer in fc.bg_layers.iter_mut() {
+            layer.hidden = true;
+        }
+        fc.render_frame();
+        assert!(fc
+            .framebuffer
+            .iter()
+            .all(|&rgb| rgb == Rgb::new(0, 0, 0xff)));
     }
 }
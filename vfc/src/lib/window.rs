@@ -0,0 +1,128 @@
+// window registers, for clipping layers to (or out of) rectangles on the screen
+
+use crate::*;
+
+pub const NUM_WINDOWS: usize = 2;
+
+// a rectangle on the screen, bounds inclusive. empty when left > right or top > bottom
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Window {
+    pub left: u8,
+    pub right: u8,
+    pub top: u8,
+    pub bottom: u8,
+}
+
+impl Default for Window {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl Window {
+    pub fn new(left: u8, top: u8, right: u8, bottom: u8) -> Window {
+        Window {
+            left,
+            right,
+            top,
+            bottom,
+        }
+    }
+
+    pub fn empty() -> Window {
+        Window::new(u8::MAX, u8::MAX, 0, 0)
+    }
+
+    pub fn contains(&self, x: u8, y: u8) -> bool {
+        x >= self.left && x <= self.right && y >= self.top && y <= self.bottom
+    }
+
+    // the bytes the bus and save states use: left, right, top, bottom
+    pub fn to_bytes(&self) -> [u8; 4] {
+        [self.left, self.right, self.top, self.bottom]
+    }
+
+    pub fn from_bytes([left, right, top, bottom]: [u8; 4]) -> Window {
+        Window {
+            left,
+            right,
+            top,
+            bottom,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum WindowMode {
+    // the layer ignores the windows
+    #[default]
+    Off,
+    // the layer only shows inside the selected windows
+    Inside,
+    // the layer only shows outside the selected windows
+    Outside,
+}
+
+impl WindowMode {
+    // the byte save states use
+    pub fn to_byte(&self) -> u8 {
+        *self as u8
+    }
+
+    pub fn from_byte(value: u8) -> Option<WindowMode> {
+        match value {
+            0 => Some(WindowMode::Off),
+            1 => Some(WindowMode::Inside),
+            2 => Some(WindowMode::Outside),
+            _ => None,
+        }
+    }
+}
+
+// which windows clip a layer, and how
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct WindowMask {
+    pub mode: WindowMode,
+    pub windows: [bool; NUM_WINDOWS],
+}
+
+impl WindowMask {
+    pub fn inside(window_index: usize) -> WindowMask {
+        let mut mask = WindowMask {
+            mode: WindowMode::Inside,
+            ..Default::default()
+        };
+        mask.windows[window_index] = true;
+        mask
+    }
+
+    // whether a layer with this mask is visible at a pixel, given the window registers
+    pub fn allows(&self, windows: &[Window], x: u8, y: u8) -> bool {
+        let inside = || {
+            windows
+                .iter()
+                .zip(self.windows.iter())
+                .any(|(window, &selected)| selected && window.contains(x, y))
+        };
+
+        match self.mode {
+            WindowMode::Off => true,
+            WindowMode::Inside => inside(),
+            WindowMode::Outside => !inside(),
+        }
+    }
+
+    pub fn outside(window_index: usize) -> WindowMask {
+        WindowMask {
+            mode: WindowMode::Outside,
+            ..WindowMask::inside(window_index)
+        }
+    }
+}
+
+impl Vfc {
+    // whether a layer with this mask is visible at a pixel
+    pub fn window_allows(&self, mask: &WindowMask, x: u8, y: u8) -> bool {
+        mask.allows(&self.windows, x, y)
+    }
+}